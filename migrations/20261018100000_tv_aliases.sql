@@ -0,0 +1 @@
+ALTER TABLE tv ADD COLUMN aliases varchar(1024);
@@ -0,0 +1 @@
+ALTER TABLE tv_seed ADD COLUMN size INTEGER;
@@ -0,0 +1,20 @@
+-- blank tvdbids of the old admin form are no shared id, the index allows many NULLs
+UPDATE tv SET tvdbid = NULL WHERE TRIM(tvdbid) = '';
+-- shows sharing a tvdbid are merged into the oldest one before the index is added
+UPDATE tv_seed SET tv_id = (
+    SELECT MIN(keep.id) FROM tv keep JOIN tv dup ON dup.tvdbid = keep.tvdbid WHERE dup.id = tv_seed.tv_id
+) WHERE tv_id IN (
+    SELECT id FROM tv WHERE tvdbid IS NOT NULL AND id NOT IN (SELECT MIN(id) FROM tv GROUP BY tvdbid)
+);
+-- a seed found under both shows is kept once
+DELETE FROM tv_seed WHERE url IS NOT NULL AND tv_id IN (
+    SELECT MIN(id) FROM tv WHERE tvdbid IS NOT NULL GROUP BY tvdbid HAVING COUNT(*) > 1
+) AND id NOT IN (SELECT MIN(id) FROM tv_seed GROUP BY tv_id, url);
+UPDATE tv SET url = (
+    SELECT dup.url FROM tv dup WHERE dup.tvdbid = tv.tvdbid AND dup.url IS NOT NULL ORDER BY dup.id LIMIT 1
+) WHERE url IS NULL AND tvdbid IS NOT NULL;
+UPDATE tv SET aliases = (
+    SELECT GROUP_CONCAT(dup.aliases, ',') FROM tv dup WHERE dup.tvdbid = tv.tvdbid AND dup.aliases IS NOT NULL
+) WHERE tvdbid IS NOT NULL AND id IN (SELECT MIN(id) FROM tv GROUP BY tvdbid);
+DELETE FROM tv WHERE tvdbid IS NOT NULL AND id NOT IN (SELECT MIN(id) FROM tv GROUP BY tvdbid);
+CREATE UNIQUE INDEX tv_tvdbid ON tv (tvdbid);
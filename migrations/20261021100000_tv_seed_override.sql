@@ -0,0 +1,16 @@
+ALTER TABLE tv_seed ADD COLUMN info_hash varchar(64);
+ALTER TABLE tv_seed ADD COLUMN season INTEGER;
+ALTER TABLE tv_seed ADD COLUMN pinned BOOLEAN;
+ALTER TABLE tv_seed ADD COLUMN manual BOOLEAN;
+
+CREATE TABLE tv_seed_override
+(
+    id        INTEGER PRIMARY KEY AUTOINCREMENT,
+    tv_id     INTEGER,
+    info_hash varchar(64),
+    blocked   BOOLEAN,
+    season    INTEGER,
+    ep        INTEGER,
+    pinned    BOOLEAN
+);
+CREATE UNIQUE INDEX tv_seed_override_info_hash ON tv_seed_override (tv_id, info_hash);
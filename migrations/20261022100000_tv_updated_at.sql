@@ -0,0 +1 @@
+ALTER TABLE tv ADD COLUMN updated_at varchar(32);
@@ -0,0 +1,2 @@
+ALTER TABLE tv ADD COLUMN sonarr_id INTEGER;
+ALTER TABLE tv ADD COLUMN removed BOOLEAN;
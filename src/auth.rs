@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::web::Query;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::model::OperationResponse;
+
+lazy_static! {
+    static ref AUTH: RwLock<AuthConfig> = RwLock::new(AuthConfig::default());
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// basic auth of the admin page and api, off unless both are set
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// `apikey` Torznab clients send to `/api` and download links, any key when unset
+    pub api_key: Option<String>,
+}
+
+pub fn init(config: AuthConfig) {
+    *AUTH.write().unwrap() = config;
+}
+
+pub fn api_key() -> Option<String> {
+    AUTH.read().unwrap().api_key.clone()
+}
+
+/// The response refusing a request to `path` (without the base path), `None` when allowed.
+pub fn reject(path: &str, query: &str, authorization: Option<&str>) -> Option<HttpResponse> {
+    let auth = AUTH.read().unwrap().clone();
+    if path == "/health" {
+        return None;
+    }
+
+    if path == "/api" || path.starts_with("/download/") {
+        let expected = auth.api_key.as_deref()?;
+        let given = Query::<HashMap<String, String>>::from_query(query)
+            .ok()
+            .and_then(|query| query.get("apikey").cloned());
+        if given.as_deref() == Some(expected) {
+            return None;
+        }
+        return Some(if path == "/api" {
+            HttpResponse::build(StatusCode::UNAUTHORIZED)
+                .content_type("application/xml")
+                .body(r#"<error code="100" description="Incorrect user credentials"/>"#)
+        } else {
+            HttpResponse::build(StatusCode::UNAUTHORIZED)
+                .json(OperationResponse::failure("incorrect api key"))
+        });
+    }
+
+    let (username, password) = match (&auth.username, &auth.password) {
+        (Some(username), Some(password)) => (username, password),
+        _ => return None,
+    };
+    let credentials = authorization
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| base64::decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    if credentials.as_deref() == Some(format!("{}:{}", username, password).as_str()) {
+        return None;
+    }
+    Some(
+        HttpResponse::build(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Basic realm=\"wkavu\"")
+            .json(OperationResponse::failure("authentication required")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject() {
+        init(AuthConfig {
+            username: Some("admin".to_string()),
+            password: Some("secret".to_string()),
+            api_key: Some("KEY".to_string()),
+        });
+
+        assert!(reject("/health", "", None).is_none());
+        assert!(reject("/api", "t=caps&apikey=KEY", None).is_none());
+        assert!(reject("/download/1", "apikey=KEY", None).is_none());
+        assert_eq!(
+            reject("/api", "t=caps&apikey=nope", None).unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let basic = format!("Basic {}", base64::encode("admin:secret"));
+        assert!(reject("/admin/tvs", "", Some(&basic)).is_none());
+        let resp = reject("/index.html", "", Some("Basic YWRtaW46d3Jvbmc=")).unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(resp.headers().contains_key(header::WWW_AUTHENTICATE));
+
+        init(AuthConfig::default());
+        assert!(reject("/api", "t=caps", None).is_none());
+        assert!(reject("/admin/tvs", "", None).is_none());
+    }
+}
@@ -0,0 +1,229 @@
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use headless_chrome::{Browser, Tab};
+use log::{info, warn};
+
+/// Tabs are closed and replaced after this many pages to keep memory in check.
+const TAB_MAX_USES: usize = 20;
+
+lazy_static! {
+    pub static ref POOL: BrowserPool = BrowserPool::new(PoolConfig::default());
+}
+
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// max tabs open at the same time, further fetches wait for a free tab
+    pub tabs: usize,
+    /// the browser is shut down after being unused this long
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            tabs: 2,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+struct IdleTab {
+    tab: Arc<Tab>,
+    uses: usize,
+}
+
+struct PoolState {
+    config: PoolConfig,
+    browser: Option<Arc<Browser>>,
+    idle: Vec<IdleTab>,
+    busy: usize,
+    /// a browser is being launched outside the lock, acquires wait for it
+    launching: bool,
+    last_used: Instant,
+}
+
+/// A long-lived Chrome process shared by all resolver runs, handing out recycled tabs.
+pub struct BrowserPool {
+    state: Mutex<PoolState>,
+    released: Condvar,
+}
+
+impl BrowserPool {
+    fn new(config: PoolConfig) -> Self {
+        BrowserPool {
+            state: Mutex::new(PoolState {
+                config,
+                browser: None,
+                idle: vec![],
+                busy: 0,
+                launching: false,
+                last_used: Instant::now(),
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn configure(&self, config: PoolConfig) {
+        info!("browser pool config:{:?}", config);
+        self.state.lock().unwrap().config = config;
+    }
+
+    /// Blocks until a tab is free, launching or relaunching the browser when needed.
+    pub fn acquire(&self) -> Result<PooledTab> {
+        let mut state = self.state.lock().unwrap();
+        while state.busy >= state.config.tabs.max(1) {
+            state = self.released.wait(state).unwrap();
+        }
+        state.busy += 1;
+        drop(state);
+
+        match self.open_tab() {
+            Ok((tab, uses)) => Ok(PooledTab {
+                pool: self,
+                tab: Some(tab),
+                uses: uses + 1,
+                broken: false,
+            }),
+            Err(e) => {
+                self.state.lock().unwrap().busy -= 1;
+                self.released.notify_one();
+                Err(e)
+            }
+        }
+    }
+
+    /// Hands out an idle or new tab. Health checks, launches and new tabs talk to Chrome,
+    /// so they run without the lock and can't hold up `release` or the reaper.
+    fn open_tab(&self) -> Result<(Arc<Tab>, usize)> {
+        loop {
+            let mut state = self.state.lock().unwrap();
+            while state.launching {
+                state = self.released.wait(state).unwrap();
+            }
+            let browser = state.browser.clone();
+            drop(state);
+
+            let alive = browser
+                .as_ref()
+                .map_or(false, |browser| browser.get_version().is_ok());
+            if let (true, Some(browser)) = (alive, &browser) {
+                if let Some(idle) = self.state.lock().unwrap().idle.pop() {
+                    return Ok((idle.tab, idle.uses));
+                }
+                let tab = browser.new_tab().map_err(|e| anyhow!("{}", e))?;
+                return Ok((tab, 0));
+            }
+
+            let mut state = self.state.lock().unwrap();
+            let unchanged = match (&state.browser, &browser) {
+                (Some(current), Some(checked)) => Arc::ptr_eq(current, checked),
+                (None, None) => true,
+                _ => false,
+            };
+            if state.launching || !unchanged {
+                // another acquire is already replacing it
+                continue;
+            }
+            if browser.is_some() {
+                warn!("browser is not responding, relaunching");
+            } else {
+                info!("launching browser");
+            }
+            let idle = std::mem::take(&mut state.idle);
+            let dead = state.browser.take();
+            state.launching = true;
+            drop(state);
+            drop((idle, dead, browser));
+            let launched = launch();
+
+            let mut state = self.state.lock().unwrap();
+            state.launching = false;
+            self.released.notify_all();
+            let (browser, tab) = launched?;
+            state.browser = Some(Arc::new(browser));
+            return Ok((tab, 0));
+        }
+    }
+
+    fn release(&self, tab: Arc<Tab>, uses: usize, broken: bool) {
+        let recycle = !broken && uses < TAB_MAX_USES && tab.navigate_to("about:blank").is_ok();
+        if !recycle {
+            if let Err(e) = tab.close(true) {
+                warn!("can't close tab: {}", e);
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.busy -= 1;
+        state.last_used = Instant::now();
+        if recycle && state.browser.is_some() {
+            state.idle.push(IdleTab { tab, uses });
+        }
+        drop(state);
+        self.released.notify_one();
+    }
+
+    /// Shuts the browser down once it has been idle longer than the configured timeout.
+    pub fn start_reaper(&'static self) {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(30));
+
+            let mut state = self.state.lock().unwrap();
+            if state.browser.is_some()
+                && state.busy == 0
+                && !state.launching
+                && state.last_used.elapsed() >= state.config.idle_timeout
+            {
+                info!("shutting down idle browser");
+                let idle = std::mem::take(&mut state.idle);
+                let browser = state.browser.take();
+                // dropping the browser kills Chrome, which can take a while
+                drop(state);
+                drop((idle, browser));
+            }
+        });
+    }
+}
+
+/// Starts Chrome with the tab it opens on startup, which is recycled like the others.
+fn launch() -> Result<(Browser, Arc<Tab>)> {
+    let browser = Browser::default().map_err(|e| anyhow!("{}", e))?;
+    let tab = browser
+        .wait_for_initial_tab()
+        .map_err(|e| anyhow!("{}", e))?;
+    Ok((browser, tab))
+}
+
+/// A tab borrowed from the pool, handed back when dropped.
+pub struct PooledTab<'a> {
+    pool: &'a BrowserPool,
+    tab: Option<Arc<Tab>>,
+    uses: usize,
+    broken: bool,
+}
+
+impl PooledTab<'_> {
+    /// Closes the tab instead of recycling it, e.g. after a failed page.
+    pub fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+}
+
+impl Deref for PooledTab<'_> {
+    type Target = Tab;
+
+    fn deref(&self) -> &Tab {
+        self.tab.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledTab<'_> {
+    fn drop(&mut self) {
+        if let Some(tab) = self.tab.take() {
+            self.pool.release(tab, self.uses, self.broken);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use log::{info, warn};
+
+lazy_static! {
+    static ref FOLDER: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Sets the folder downloaded `.torrent` files are kept in, caching is off without one.
+pub fn init(folder: Option<&str>) -> Result<()> {
+    if let Some(folder) = folder {
+        fs::create_dir_all(folder)?;
+        info!("torrent cache folder:{}", folder);
+    }
+    *FOLDER.write().unwrap() = folder.map(PathBuf::from);
+    Ok(())
+}
+
+fn path(info_hash: &str) -> Option<PathBuf> {
+    let info_hash = info_hash.to_lowercase();
+    if info_hash.is_empty() || !info_hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    FOLDER
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|folder| folder.join(format!("{}.torrent", info_hash)))
+}
+
+pub fn save(info_hash: &str, bytes: &[u8]) {
+    if let Some(path) = path(info_hash) {
+        if let Err(e) = fs::write(&path, bytes) {
+            warn!("can't cache torrent {:?}: {}", path, e);
+        }
+    }
+}
+
+pub fn load(info_hash: &str) -> Option<Vec<u8>> {
+    path(info_hash).and_then(|path| fs::read(path).ok())
+}
+
+pub fn contains(info_hash: &str) -> bool {
+    path(info_hash).map_or(false, |path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let folder = std::env::temp_dir().join("wkavu-cache-test");
+        init(folder.to_str()).unwrap();
+
+        save("ABCDEF", b"d4:infode");
+        assert_eq!(load("abcdef").unwrap(), b"d4:infode");
+        assert!(contains("abcdef"));
+        assert!(!contains("123456"));
+        assert!(load("123456").is_none());
+        assert!(load("../etc/passwd").is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use encoding_rs::{Encoding, GB18030, UTF_8};
+use regex::bytes::Regex;
+
+/// Bytes scanned for a `<meta>` charset, like browsers do.
+const META_SCAN_LIMIT: usize = 4096;
+
+lazy_static! {
+    static ref META_CHARSET: Regex =
+        Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?\s*([a-z0-9_:.-]+)"#).unwrap();
+}
+
+/// Decodes a page to UTF-8.
+///
+/// A configured charset always wins, then a BOM. Otherwise the header and `<meta>` charsets
+/// are tried in order, then UTF-8 and GB18030, and the first one decoding without errors
+/// is used, so pages with a missing or wrong header still decode.
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>, charset: Option<&str>) -> String {
+    if let Some(encoding) = charset.and_then(|c| Encoding::for_label(c.trim().as_bytes())) {
+        return encoding.decode_with_bom_removal(bytes).0.into_owned();
+    }
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding.decode_with_bom_removal(bytes).0.into_owned();
+    }
+
+    let candidates = [
+        content_type.and_then(header_charset),
+        meta_charset(bytes),
+        Some(UTF_8),
+        Some(GB18030),
+    ];
+
+    let mut fallback = None;
+    for encoding in candidates.into_iter().flatten() {
+        let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+        if !had_errors {
+            return text.into_owned();
+        }
+        fallback.get_or_insert(text);
+    }
+    fallback.unwrap().into_owned()
+}
+
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+}
+
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(META_SCAN_LIMIT)];
+    META_CHARSET
+        .captures(head)
+        .and_then(|captures| Encoding::for_label(&captures[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::GBK;
+
+    use super::*;
+
+    fn gbk(text: &str) -> Vec<u8> {
+        GBK.encode(text).0.into_owned()
+    }
+
+    #[test]
+    fn test_header_charset() {
+        let bytes = gbk("<p>第01集</p>");
+        let html = decode_html(&bytes, Some("text/html; charset=GBK"), None);
+        assert!(html.contains("第01集"));
+    }
+
+    #[test]
+    fn test_meta_charset() {
+        let bytes = gbk(
+            r#"<meta http-equiv="Content-Type" content="text/html; charset=gb2312"><p>第02集</p>"#,
+        );
+        let html = decode_html(&bytes, Some("text/html"), None);
+        assert!(html.contains("第02集"));
+    }
+
+    #[test]
+    fn test_wrong_header() {
+        let bytes = gbk(r#"<meta charset="gbk"><p>第03集</p>"#);
+        let html = decode_html(&bytes, Some("text/html; charset=utf-8"), None);
+        assert!(html.contains("第03集"));
+    }
+
+    #[test]
+    fn test_no_hint() {
+        let bytes = gbk("<p>第04集</p>");
+        let html = decode_html(&bytes, None, None);
+        assert!(html.contains("第04集"));
+    }
+
+    #[test]
+    fn test_utf8_bom() {
+        let mut bytes = vec![0xEF, 0xBB, 0xBF];
+        bytes.extend_from_slice("<p>第05集</p>".as_bytes());
+        let html = decode_html(&bytes, Some("text/html; charset=gbk"), None);
+        assert_eq!(html, "<p>第05集</p>");
+    }
+
+    #[test]
+    fn test_override() {
+        let bytes = gbk("<p>第06集</p>");
+        let html = decode_html(&bytes, Some("text/html; charset=utf-8"), Some("gbk"));
+        assert!(html.contains("第06集"));
+    }
+}
@@ -0,0 +1,569 @@
+use std::path::Path;
+
+use actix_web::web;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use rbatis::crud::CRUD;
+
+use crate::config::Config;
+use crate::define;
+use crate::error::AppError;
+use crate::global;
+use crate::http::ApiRequest;
+use crate::library::{self, ExportOptions, Format};
+use crate::model::{Tv, TvSeed};
+use crate::resolver::Resolver;
+use crate::torznab::TorznabProvider;
+use crate::tvs;
+
+fn number(value: String) -> Result<(), String> {
+    value
+        .parse::<i64>()
+        .map(|_| ())
+        .map_err(|_| format!("{} is not a number", value))
+}
+
+fn number_arg(name: &str) -> Arg<'_, '_> {
+    Arg::with_name(name)
+        .long(name)
+        .takes_value(true)
+        .validator(number)
+}
+
+/// Headless commands, sharing the database and resolver with the server.
+pub fn subcommands<'a, 'b>() -> Vec<App<'a, 'b>> {
+    vec![
+        SubCommand::with_name("serve")
+            .about("Runs the http server and the fetch schedule, also without a subcommand"),
+        SubCommand::with_name("migrate").about("Applies database migrations"),
+        SubCommand::with_name("fetch")
+            .about("Fetches the seeds of every show, or of one")
+            .arg(number_arg("tv").value_name("ID")),
+        SubCommand::with_name("tv")
+            .about("Manages shows")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("add")
+                    .about("Adds a show, checked like the admin api")
+                    .arg(
+                        Arg::with_name("name")
+                            .long("name")
+                            .takes_value(true)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("tvdbid")
+                            .long("tvdbid")
+                            .takes_value(true)
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("tvname")
+                            .long("tvname")
+                            .takes_value(true)
+                            .help("Name in Sonarr, defaults to --name"),
+                    )
+                    .arg(
+                        Arg::with_name("url")
+                            .long("url")
+                            .takes_value(true)
+                            .help("Show page, searched by name when missing"),
+                    )
+                    .arg(
+                        Arg::with_name("aliases")
+                            .long("aliases")
+                            .takes_value(true)
+                            .help("Comma separated"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("list").about("Lists shows").arg(
+                    Arg::with_name("keyword")
+                        .long("keyword")
+                        .takes_value(true)
+                        .help("Matches name, tvname, aliases or the exact tvdbid"),
+                ),
+            )
+            .subcommand(
+                SubCommand::with_name("remove")
+                    .about("Deletes a show and its seeds")
+                    .arg(
+                        Arg::with_name("id")
+                            .value_name("ID")
+                            .required(true)
+                            .validator(number),
+                    ),
+            ),
+        SubCommand::with_name("seeds")
+            .about("Shows seeds")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("list")
+                    .about("Lists the seeds of a show")
+                    .arg(
+                        Arg::with_name("tvid")
+                            .value_name("TVID")
+                            .required(true)
+                            .validator(number),
+                    )
+                    .arg(number_arg("season"))
+                    .arg(number_arg("ep")),
+            ),
+        SubCommand::with_name("torznab")
+            .about("Runs Torznab requests")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("query")
+                    .about("Prints the feed xml Sonarr would get")
+                    .arg(
+                        Arg::with_name("t")
+                            .long("t")
+                            .takes_value(true)
+                            .possible_values(&["caps", "search", "tvsearch"])
+                            .default_value("tvsearch"),
+                    )
+                    .arg(Arg::with_name("tvdbid").long("tvdbid").takes_value(true))
+                    .arg(Arg::with_name("q").long("q").takes_value(true))
+                    .arg(number_arg("season"))
+                    .arg(number_arg("ep"))
+                    .arg(number_arg("offset"))
+                    .arg(number_arg("limit"))
+                    .arg(
+                        Arg::with_name("base-url")
+                            .long("base-url")
+                            .takes_value(true)
+                            .help("Prefix of download links, defaults to the local server"),
+                    ),
+            ),
+        SubCommand::with_name("print-config")
+            .about("Prints the config merged from the file, environment and options")
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["toml", "yaml"])
+                    .default_value("toml"),
+            )
+            .arg(
+                Arg::with_name("show-secrets")
+                    .long("show-secrets")
+                    .help("Prints passwords and api keys instead of ***"),
+            ),
+        SubCommand::with_name("validate-defines")
+            .about("Validates embedded and external site defines")
+            .arg(
+                Arg::with_name("folder")
+                    .value_name("FOLDER")
+                    .help("External define folder, defaults to --define-folder"),
+            ),
+        SubCommand::with_name("export")
+            .about("Exports the show library")
+            .arg(
+                Arg::with_name("file")
+                    .value_name("FILE")
+                    .help("Output file, stdout when missing"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["json", "yaml", "csv"])
+                    .help("Defaults to the file extension, or json"),
+            )
+            .arg(Arg::with_name("seeds").long("seeds").help("Includes seeds"))
+            .arg(
+                Arg::with_name("overrides")
+                    .long("overrides")
+                    .help("Includes blocked, pinned and remapped seeds"),
+            ),
+        SubCommand::with_name("import")
+            .about("Imports a show library, merging shows by tvdbid")
+            .arg(Arg::with_name("file").value_name("FILE").required(true))
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["json", "yaml", "csv"])
+                    .help("Defaults to the file extension, or json"),
+            )
+            .arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Prints the changes without saving them"),
+            ),
+    ]
+}
+
+/// Runs a subcommand that needs the database, `None` when the server should start.
+pub async fn run(matches: &ArgMatches<'_>, config: &Config) -> Option<i32> {
+    let code = match matches.subcommand() {
+        ("migrate", Some(_)) => {
+            println!("database migrated");
+            0
+        }
+        ("fetch", Some(m)) => fetch(m).await,
+        ("tv", Some(m)) => match m.subcommand() {
+            ("add", Some(m)) => tv_add(m).await,
+            ("list", Some(m)) => tv_list(m).await,
+            ("remove", Some(m)) => tv_remove(m).await,
+            _ => 1,
+        },
+        ("seeds", Some(m)) => match m.subcommand() {
+            ("list", Some(m)) => seed_list(m).await,
+            _ => 1,
+        },
+        ("torznab", Some(m)) => match m.subcommand() {
+            ("query", Some(m)) => torznab_query(m, config).await,
+            _ => 1,
+        },
+        ("export", Some(m)) => export_library(m).await,
+        ("import", Some(m)) => import_library(m).await,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Prints an error, by field for invalid input, and returns the exit code.
+fn report(e: AppError) -> i32 {
+    match e {
+        AppError::Invalid(errors) => {
+            let mut errors: Vec<_> = errors.into_iter().collect();
+            errors.sort();
+            for (field, msg) in errors {
+                eprintln!("{}: {}", field, msg);
+            }
+        }
+        e => eprintln!("{}", e),
+    }
+    1
+}
+
+async fn fetch(matches: &ArgMatches<'_>) -> i32 {
+    let resolver = Resolver::new();
+    if let Ok(id) = value_t!(matches, "tv", i64) {
+        if let Err(e) = tvs::find_tv(id).await {
+            return report(e);
+        }
+        return match resolver.fetch_by_tv(id).await {
+            Ok(()) => 0,
+            Err(e) => report(e.into()),
+        };
+    }
+
+    let tvs: Vec<Tv> = match global::RB.fetch_list().await {
+        Ok(tvs) => tvs,
+        Err(e) => return report(e.into()),
+    };
+    resolver
+        .fetch_all(tvs.into_iter().filter_map(|tv| tv.id).collect())
+        .await;
+    0
+}
+
+async fn tv_add(matches: &ArgMatches<'_>) -> i32 {
+    let name = matches.value_of("name").unwrap();
+    let tv = Tv {
+        id: None,
+        tvdbid: matches.value_of("tvdbid").map(|id| id.trim().to_string()),
+        tvname: Some(matches.value_of("tvname").unwrap_or(name).to_string()),
+        url: tvs::non_empty(matches.value_of("url").map(String::from)),
+        name: Some(name.to_string()),
+        aliases: matches.value_of("aliases").map(String::from),
+        updated_at: None,
+        sonarr_id: None,
+        removed: None,
+    };
+    match tvs::save_tv(tv).await {
+        Ok(()) => {
+            println!("added {}", name);
+            0
+        }
+        Err(e) => report(e),
+    }
+}
+
+async fn tv_list(matches: &ArgMatches<'_>) -> i32 {
+    let wrapper =
+        tvs::tv_filter(matches.value_of("keyword").map(String::from)).order_by(true, &[Tv::id()]);
+    let tvs: Vec<Tv> = match global::RB.fetch_list_by_wrapper(wrapper).await {
+        Ok(tvs) => tvs,
+        Err(e) => return report(e.into()),
+    };
+    for tv in tvs {
+        println!(
+            "{}\t{}\t{}\t{}",
+            tv.id.unwrap_or_default(),
+            tv.tvdbid.unwrap_or_default(),
+            tv.name.unwrap_or_default(),
+            tv.url.unwrap_or_else(|| "-".to_string())
+        );
+    }
+    0
+}
+
+async fn tv_remove(matches: &ArgMatches<'_>) -> i32 {
+    let id = value_t!(matches, "id", i64).unwrap();
+    match tvs::remove_tv(id).await {
+        Ok(()) => {
+            println!("removed {}", id);
+            0
+        }
+        Err(e) => report(e),
+    }
+}
+
+async fn seed_list(matches: &ArgMatches<'_>) -> i32 {
+    let tv_id = value_t!(matches, "tvid", i64).unwrap();
+    let mut wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), tv_id);
+    if let Ok(season) = value_t!(matches, "season", i64) {
+        wrapper = wrapper.and().eq(TvSeed::season(), season);
+    }
+    if let Ok(ep) = value_t!(matches, "ep", i64) {
+        wrapper = wrapper.and().eq(TvSeed::ep(), ep);
+    }
+    wrapper = wrapper.order_by(true, &[TvSeed::ep()]);
+    let seeds: Vec<TvSeed> = match global::RB.fetch_list_by_wrapper(wrapper).await {
+        Ok(seeds) => seeds,
+        Err(e) => return report(e.into()),
+    };
+
+    for seed in seeds {
+        let flag = match (seed.pinned, seed.manual) {
+            (Some(true), _) => "pinned",
+            (_, Some(true)) => "manual",
+            _ => "-",
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            seed.id.unwrap_or_default(),
+            seed.season
+                .map_or("-".to_string(), |season| season.to_string()),
+            seed.ep.unwrap_or_default(),
+            seed.size.unwrap_or_default(),
+            flag,
+            seed.name.unwrap_or_default()
+        );
+    }
+    0
+}
+
+async fn torznab_query(matches: &ArgMatches<'_>, config: &Config) -> i32 {
+    let request = ApiRequest {
+        t: matches.value_of("t").unwrap().to_string(),
+        q: matches.value_of("q").map(String::from),
+        tvdbid: matches.value_of("tvdbid").map(String::from),
+        season: value_t!(matches, "season", i32).ok(),
+        ep: value_t!(matches, "ep", i32).ok(),
+        offset: value_t!(matches, "offset", u64).ok(),
+        limit: value_t!(matches, "limit", u64).ok(),
+    };
+    let base_url = matches
+        .value_of("base-url")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| {
+            format!(
+                "http://localhost:{}{}",
+                config.server.port, config.server.base_path
+            )
+        });
+
+    match TorznabProvider::new()
+        .handle(&web::Query(request), &base_url)
+        .await
+    {
+        Ok(xml) => {
+            println!("{}", xml);
+            0
+        }
+        Err(e) => report(e),
+    }
+}
+
+fn library_format(matches: &ArgMatches<'_>) -> Format {
+    matches
+        .value_of("format")
+        .and_then(|format| format.parse().ok())
+        .or_else(|| {
+            matches
+                .value_of("file")
+                .and_then(|file| Format::from_path(Path::new(file)))
+        })
+        .unwrap_or(Format::Json)
+}
+
+async fn export_library(matches: &ArgMatches<'_>) -> i32 {
+    let options = ExportOptions {
+        seeds: matches.is_present("seeds"),
+        overrides: matches.is_present("overrides"),
+    };
+    let result = library::export(&options)
+        .await
+        .and_then(|library| library::render(&library, library_format(matches)));
+    let content = match result {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    match matches.value_of("file") {
+        Some(file) => {
+            if let Err(e) = std::fs::write(file, content) {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+        None => print!("{}", content),
+    }
+    0
+}
+
+async fn import_library(matches: &ArgMatches<'_>) -> i32 {
+    let file = matches.value_of("file").unwrap();
+    let dry_run = matches.is_present("dry-run");
+    let result = match std::fs::read_to_string(file) {
+        Ok(content) => library::parse(&content, library_format(matches)),
+        Err(e) => Err(e.into()),
+    };
+    let report = match result {
+        Ok(library) => library::import(&library, dry_run).await,
+        Err(e) => Err(e),
+    };
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    for change in &report.changes {
+        println!(
+            "{:?} {} {} {}",
+            change.action,
+            change.tvdbid,
+            change.name,
+            change.fields.join(", ")
+        );
+    }
+    println!(
+        "{}{} created, {} updated, {} unchanged, {} invalid, {} seeds, {} overrides",
+        if dry_run { "dry run: " } else { "" },
+        report.created,
+        report.updated,
+        report.unchanged,
+        report.invalid,
+        report.seeds,
+        report.overrides
+    );
+    if report.invalid > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+pub fn validate_defines(folder: Option<&str>) -> i32 {
+    let (size, problems) = define::check(folder.map(Path::new));
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    println!(
+        "{} defines checked, {} problems found",
+        size,
+        problems.len()
+    );
+
+    if problems.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ArgMatches<'static>, clap::Error> {
+        App::new("wkavu")
+            .subcommands(subcommands())
+            .get_matches_from_safe(args)
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let matches = parse(&[
+            "wkavu",
+            "tv",
+            "add",
+            "--name",
+            "Show",
+            "--tvdbid",
+            "1234",
+            "--aliases",
+            "A,B",
+        ])
+        .unwrap();
+        let (_, tv) = matches.subcommand();
+        let (name, add) = tv.unwrap().subcommand();
+        assert_eq!(name, "add");
+        assert_eq!(add.unwrap().value_of("tvdbid"), Some("1234"));
+
+        let matches = parse(&["wkavu", "torznab", "query", "--tvdbid", "1234"]).unwrap();
+        let query = matches
+            .subcommand_matches("torznab")
+            .and_then(|m| m.subcommand_matches("query"))
+            .unwrap();
+        assert_eq!(query.value_of("t"), Some("tvsearch"));
+
+        assert!(parse(&["wkavu", "tv", "add", "--name", "Show"]).is_err());
+        assert!(parse(&["wkavu", "fetch", "--tv", "abc"]).is_err());
+        assert!(parse(&["wkavu", "seeds", "list", "1", "--ep", "x"]).is_err());
+        assert!(parse(&["wkavu", "torznab", "query", "--t", "movie"]).is_err());
+    }
+
+    #[async_std::test]
+    async fn test_tv_commands() {
+        global::connect_test().await;
+        let config = Config::default();
+
+        let add = parse(&[
+            "wkavu",
+            "tv",
+            "add",
+            "--name",
+            "三体",
+            "--tvdbid",
+            "421026",
+            "--aliases",
+            "3体",
+        ])
+        .unwrap();
+        assert_eq!(run(&add, &config).await, Some(0));
+        // the tvdbid is taken now
+        assert_eq!(run(&add, &config).await, Some(1));
+
+        let shows: Vec<Tv> = global::RB
+            .fetch_list_by_wrapper(tvs::tv_filter(Some("3体".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(shows.len(), 1);
+        assert_eq!(shows[0].tvname.as_deref(), Some("三体"));
+        let id = shows[0].id.unwrap().to_string();
+
+        let list = parse(&["wkavu", "tv", "list", "--keyword", "三体"]).unwrap();
+        assert_eq!(run(&list, &config).await, Some(0));
+        let query = parse(&["wkavu", "torznab", "query", "--tvdbid", "1"]).unwrap();
+        assert_eq!(run(&query, &config).await, Some(0));
+
+        let remove = parse(&["wkavu", "tv", "remove", &id]).unwrap();
+        assert_eq!(run(&remove, &config).await, Some(0));
+        assert_eq!(run(&remove, &config).await, Some(1));
+        let shows: Vec<Tv> = global::RB
+            .fetch_list_by_wrapper(tvs::tv_filter(Some("421026".to_string())))
+            .await
+            .unwrap();
+        assert!(shows.is_empty());
+    }
+}
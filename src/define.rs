@@ -0,0 +1,597 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, error, info};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+use scraper::Selector;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolverDefine {
+    pub id: String,
+    pub name: String,
+    pub domains: Vec<String>,
+    pub timeout: u64,
+    pub search: ResolverSearchDefine,
+    pub provider: Option<ContentProviderType>,
+    pub limit: Option<ResolverLimitDefine>,
+    pub http: Option<ResolverHttpDefine>,
+    pub magnet: Option<ResolverMagnetDefine>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ResolverMagnetDefine {
+    /// appended to the global trackers when the feed is rendered
+    #[serde(default)]
+    pub trackers: Vec<String>,
+    /// keeps the trackers of the site's magnets
+    #[serde(default)]
+    pub keep_trackers: bool,
+    /// keeps `dn` of the site's magnets, or the name of downloaded torrents
+    #[serde(default)]
+    pub keep_name: bool,
+}
+
+/// Options of the `Reqwest` provider.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResolverHttpDefine {
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// raw `Cookie` header, e.g. copied from a logged-in browser
+    pub cookies: Option<String>,
+    /// http, https or socks5 proxy url
+    pub proxy: Option<String>,
+    /// request timeout in seconds, the define timeout when missing
+    pub timeout: Option<u64>,
+    pub retries: Option<u32>,
+    /// milliseconds before the first retry, doubled for every further retry
+    pub backoff: Option<u64>,
+    /// decodes pages with this charset instead of the response header, e.g. `gbk`
+    pub charset: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolverLimitDefine {
+    /// max parallel requests to the site, 1 when missing
+    pub concurrency: Option<usize>,
+    /// min milliseconds between two requests to the site
+    pub delay: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ContentProviderType {
+    Chrome,
+    Reqwest,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolverRowSelectorDefine {
+    pub attr: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolverRowsDefine {
+    pub selector: String,
+    pub title: ResolverRowSelectorDefine,
+    pub url: ResolverRowSelectorDefine,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolverSearchDefine {
+    /// search page template, `{name}` is replaced with the encoded show name
+    pub url: Option<String>,
+    /// rows on the search page which link to the show page
+    pub results: Option<ResolverRowsDefine>,
+    pub wait: Option<String>,
+    /// browser actions run in order after `wait`, only by the `Chrome` provider
+    pub actions: Option<Vec<BrowserAction>>,
+    pub rows: ResolverRowsDefine,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum BrowserAction {
+    /// waits until the selector matches
+    Wait(String),
+    Click(String),
+    /// scrolls the first match into view
+    Scroll(String),
+    ScrollBottom,
+    /// evaluates a script in the page, promises are awaited
+    Eval(String),
+    /// milliseconds
+    Sleep(u64),
+    /// continues in the iframe matching the selector by opening its page
+    Frame(String),
+}
+
+#[derive(Error, Debug)]
+pub enum DefineError {
+    #[error("Invalid define {0}: {1}")]
+    Invalid(String, String),
+}
+
+#[derive(RustEmbed)]
+#[folder = "define/"]
+struct Define;
+
+const MAX_TIMEOUT: u64 = 600;
+
+lazy_static! {
+    static ref DEFINES: RwLock<Vec<ResolverDefine>> = RwLock::new(vec![]);
+}
+
+lazy_static! {
+    static ref FOLDER: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Returns a snapshot of the loaded defines.
+pub fn all() -> Vec<ResolverDefine> {
+    let defines = DEFINES.read().unwrap();
+    if defines.is_empty() {
+        drop(defines);
+        reload();
+        return DEFINES.read().unwrap().clone();
+    }
+    defines.clone()
+}
+
+/// Finds the define handling a show page.
+pub fn find(url: &str) -> Option<ResolverDefine> {
+    if DEFINES.read().unwrap().is_empty() {
+        reload();
+    }
+    DEFINES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|d| d.domains.iter().any(|domain| url.starts_with(domain)))
+        .cloned()
+}
+
+/// Sets the external define folder, whose files override embedded defines by id.
+pub fn init(folder: Option<&str>) {
+    *FOLDER.lock().unwrap() = folder.map(PathBuf::from);
+    reload();
+}
+
+/// Loads embedded and external defines again, invalid files are logged and skipped.
+pub fn reload() -> usize {
+    let folder = FOLDER.lock().unwrap().clone();
+    let (_, defines, problems) = load(folder.as_deref());
+    for problem in problems {
+        error!("{}", problem);
+    }
+    let size = defines.len();
+    *DEFINES.write().unwrap() = defines;
+    info!("define size:{}", size);
+    size
+}
+
+/// A define file, embedded or from the external folder, with its raw content.
+struct Source {
+    name: String,
+    external: bool,
+    content: Result<String>,
+}
+
+/// A problem found while validating define files.
+#[derive(Debug)]
+pub struct Problem {
+    pub source: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.source, self.message)
+    }
+}
+
+/// Reads and validates every define file once, returns the file count, the valid defines
+/// merged by id and all problems.
+fn load(folder: Option<&Path>) -> (usize, Vec<ResolverDefine>, Vec<Problem>) {
+    let sources = sources(folder);
+    let size = sources.len();
+    let mut defines: Vec<ResolverDefine> = vec![];
+    let mut problems = vec![];
+    let mut seen: Vec<(bool, String, String)> = vec![];
+
+    for source in sources {
+        let content = match source.content {
+            Ok(content) => content,
+            Err(e) => {
+                problems.push(Problem {
+                    source: source.name,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let define: ResolverDefine = match serde_yaml::from_str(&content) {
+            Ok(define) => define,
+            Err(e) => {
+                problems.push(Problem {
+                    source: source.name,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        if let Some((_, _, other)) = seen
+            .iter()
+            .find(|(external, id, _)| *external == source.external && *id == define.id)
+        {
+            problems.push(Problem {
+                source: source.name.to_string(),
+                message: format!("id: {} is also used by {}", define.id, other),
+            });
+        }
+        seen.push((
+            source.external,
+            define.id.to_string(),
+            source.name.to_string(),
+        ));
+
+        let messages = validate(&define);
+        if messages.is_empty() {
+            info!("load config for {} from {}", define.id, source.name);
+            merge(&mut defines, define);
+        } else {
+            debug!("skip define {}", source.name);
+        }
+        for message in messages {
+            problems.push(Problem {
+                source: source.name.to_string(),
+                message,
+            });
+        }
+    }
+
+    (size, defines, problems)
+}
+
+/// Validates embedded and external defines, returns the checked file count and all problems.
+pub fn check(folder: Option<&Path>) -> (usize, Vec<Problem>) {
+    let (size, _, problems) = load(folder);
+    (size, problems)
+}
+
+fn sources(folder: Option<&Path>) -> Vec<Source> {
+    let mut sources = vec![];
+
+    for file in Define::iter() {
+        if !is_yaml(file.as_ref()) {
+            continue;
+        }
+        let yaml = Define::get(file.as_ref()).unwrap();
+        let content = String::from_utf8(yaml.data.to_vec()).map_err(anyhow::Error::from);
+        sources.push(Source {
+            name: format!("embedded:{}", file),
+            external: false,
+            content,
+        });
+    }
+
+    if let Some(folder) = folder {
+        match fs::read_dir(folder) {
+            Ok(entries) => {
+                let mut paths: Vec<PathBuf> = entries
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.is_file() && is_yaml(&path.to_string_lossy()))
+                    .collect();
+                paths.sort();
+
+                for path in paths {
+                    sources.push(Source {
+                        name: path.display().to_string(),
+                        external: true,
+                        content: fs::read_to_string(&path).map_err(anyhow::Error::from),
+                    });
+                }
+            }
+            Err(e) => error!("can't read define folder {:?}: {}", folder, e),
+        }
+    }
+
+    sources
+}
+
+fn merge(defines: &mut Vec<ResolverDefine>, define: ResolverDefine) {
+    match defines.iter_mut().find(|d| d.id == define.id) {
+        Some(existing) => *existing = define,
+        None => defines.push(define),
+    }
+}
+
+fn is_yaml(name: &str) -> bool {
+    name.ends_with(".yml") || name.ends_with(".yaml")
+}
+
+pub fn parse(content: &str) -> Result<ResolverDefine> {
+    let define: ResolverDefine = serde_yaml::from_str(content)?;
+    let problems = validate(&define);
+    if !problems.is_empty() {
+        return Err(DefineError::Invalid(define.id, problems.join("; ")).into());
+    }
+    Ok(define)
+}
+
+/// Checks a parsed define, every problem is prefixed with the field it belongs to.
+pub fn validate(define: &ResolverDefine) -> Vec<String> {
+    let mut problems = vec![];
+
+    if define.id.trim().is_empty() {
+        problems.push("id: is empty".to_string());
+    }
+    if define.name.trim().is_empty() {
+        problems.push("name: is empty".to_string());
+    }
+
+    if define.domains.is_empty() {
+        problems.push("domains: is empty".to_string());
+    }
+    for (i, domain) in define.domains.iter().enumerate() {
+        if let Err(msg) = check_url(domain) {
+            problems.push(format!("domains[{}]: {} {}", i, domain, msg));
+        }
+    }
+
+    if define.timeout == 0 || define.timeout > MAX_TIMEOUT {
+        problems.push(format!(
+            "timeout: {} should be between 1 and {} seconds",
+            define.timeout, MAX_TIMEOUT
+        ));
+    }
+
+    if let Some(limit) = &define.limit {
+        if limit.concurrency == Some(0) {
+            problems.push("limit.concurrency: should be at least 1".to_string());
+        }
+    }
+
+    if let Some(http) = &define.http {
+        for (name, value) in &http.headers {
+            if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err()
+                || reqwest::header::HeaderValue::from_str(value).is_err()
+            {
+                problems.push(format!("http.headers.{}: is not a valid header", name));
+            }
+        }
+        if let Some(proxy) = &http.proxy {
+            if let Err(e) = reqwest::Proxy::all(proxy) {
+                problems.push(format!("http.proxy: {} is not a valid proxy: {}", proxy, e));
+            }
+        }
+        if http.timeout == Some(0) || http.timeout.map_or(false, |t| t > MAX_TIMEOUT) {
+            problems.push(format!(
+                "http.timeout: should be between 1 and {} seconds",
+                MAX_TIMEOUT
+            ));
+        }
+        if let Some(charset) = &http.charset {
+            if encoding_rs::Encoding::for_label(charset.as_bytes()).is_none() {
+                problems.push(format!("http.charset: {} is unknown", charset));
+            }
+        }
+    }
+
+    if let Some(magnet) = &define.magnet {
+        for (i, tracker) in magnet.trackers.iter().enumerate() {
+            if let Err(e) = reqwest::Url::parse(tracker) {
+                problems.push(format!(
+                    "magnet.trackers[{}]: {} is not a valid url: {}",
+                    i, tracker, e
+                ));
+            }
+        }
+    }
+
+    let search = &define.search;
+    match (&search.url, &search.results) {
+        (Some(url), Some(_)) => {
+            if !url.contains("{name}") {
+                problems.push(format!("search.url: {} has no {{name}} placeholder", url));
+            } else if let Err(msg) = check_url(&url.replace("{name}", "name")) {
+                problems.push(format!("search.url: {} {}", url, msg));
+            }
+        }
+        (Some(_), None) => problems.push("search.results: is required by search.url".to_string()),
+        (None, Some(_)) => problems.push("search.url: is required by search.results".to_string()),
+        (None, None) => {}
+    }
+
+    if let Some(wait) = &search.wait {
+        check_selector("search.wait", wait, &mut problems);
+    }
+    if let Some(actions) = &search.actions {
+        if matches!(define.provider, Some(ContentProviderType::Reqwest)) {
+            problems.push("search.actions: only run by the Chrome provider".to_string());
+        }
+        for (i, action) in actions.iter().enumerate() {
+            let field = format!("search.actions[{}]", i);
+            match action {
+                BrowserAction::Wait(selector)
+                | BrowserAction::Click(selector)
+                | BrowserAction::Scroll(selector)
+                | BrowserAction::Frame(selector) => check_selector(&field, selector, &mut problems),
+                BrowserAction::Eval(script) if script.trim().is_empty() => {
+                    problems.push(format!("{}: script is empty", field))
+                }
+                BrowserAction::Sleep(ms) if *ms > define.timeout * 1000 => {
+                    problems.push(format!("{}: sleep is longer than timeout", field))
+                }
+                _ => {}
+            }
+        }
+    }
+    check_rows("search.rows", &search.rows, &mut problems);
+    if let Some(results) = &search.results {
+        check_rows("search.results", results, &mut problems);
+    }
+
+    problems
+}
+
+fn check_url(url: &str) -> std::result::Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() != "http" && parsed.scheme() != "https" => {
+            Err("should use http or https".to_string())
+        }
+        Ok(parsed) if parsed.host_str().is_none() => Err("has no host".to_string()),
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("is not a valid url: {}", e)),
+    }
+}
+
+fn check_rows(field: &str, rows: &ResolverRowsDefine, problems: &mut Vec<String>) {
+    check_selector(&format!("{}.selector", field), &rows.selector, problems);
+    if rows.url.attr.as_ref().map_or(true, |attr| attr.is_empty()) {
+        problems.push(format!("{}.url.attr: is required", field));
+    }
+}
+
+fn check_selector(field: &str, selector: &str, problems: &mut Vec<String>) {
+    if let Err(e) = Selector::parse(selector) {
+        problems.push(format!(
+            "{}: can't parse selector `{}`: {:?}",
+            field, selector, e
+        ));
+    }
+}
+
+/// Reloads defines whenever a file in the external folder changes.
+pub fn watch() {
+    let folder = match FOLDER.lock().unwrap().clone() {
+        Some(folder) => folder,
+        None => return,
+    };
+
+    std::thread::spawn(move || {
+        let (tx, rx) = channel();
+        let mut watcher = notify::watcher(tx, Duration::from_secs(2)).unwrap();
+        if let Err(e) = watcher.watch(&folder, RecursiveMode::NonRecursive) {
+            error!("can't watch define folder {:?}: {}", folder, e);
+            return;
+        }
+        info!("watching define folder {:?}", folder);
+
+        for event in rx {
+            match event {
+                DebouncedEvent::Create(_)
+                | DebouncedEvent::Write(_)
+                | DebouncedEvent::Remove(_)
+                | DebouncedEvent::Rename(_, _) => {
+                    info!("define folder changed: {:?}", event);
+                    reload();
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_yml() {
+        let paths = fs::read_dir("./define").unwrap();
+
+        for path in paths {
+            let result = std::fs::read_to_string(path.unwrap().path()).unwrap();
+            let define: ResolverDefine = parse(&result).unwrap();
+            println!("Define:{:?}", define);
+            // Ensure that the define is parsed successfully
+            assert!(define.name.len() > 0);
+        }
+    }
+
+    #[test]
+    fn test_override_by_id() {
+        let folder = std::env::temp_dir().join("wkavu-define-test");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(
+            folder.join("bt-tt.yml"),
+            "id: bt-tt\nname: override\ndomains:\n  - https://bt-tt.example/\ntimeout: 10\nsearch:\n  rows:\n    selector: a\n    title:\n      attr:\n    url:\n      attr: href\n",
+        )
+        .unwrap();
+        fs::write(folder.join("broken.yml"), "id: broken\n").unwrap();
+
+        let (_, defines, problems) = load(Some(&folder));
+
+        assert!(problems.iter().any(|p| p.source.ends_with("broken.yml")));
+        let bt_tt = defines.iter().find(|d| d.id == "bt-tt").unwrap();
+        assert_eq!(bt_tt.name, "override");
+        assert!(defines.iter().any(|d| d.id == "domp4"));
+        assert!(!defines.iter().any(|d| d.id == "broken"));
+    }
+
+    #[test]
+    fn test_check_embedded() {
+        let (size, problems) = check(None);
+
+        assert!(size > 0);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_validate_problems() {
+        let define: ResolverDefine = serde_yaml::from_str(
+            "id: bad\nname: bad\ndomains:\n  - ftp://bad.example/\ntimeout: 0\nsearch:\n  url: https://bad.example/search\n  rows:\n    selector: a\n    title:\n      attr:\n    url:\n      attr:\n",
+        )
+        .unwrap();
+
+        let problems = validate(&define);
+
+        assert!(problems.iter().any(|p| p.starts_with("domains[0]:")));
+        assert!(problems.iter().any(|p| p.starts_with("timeout:")));
+        assert!(problems.iter().any(|p| p.starts_with("search.results:")));
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("search.rows.url.attr:")));
+    }
+
+    #[test]
+    fn test_actions() {
+        let define = parse(
+            "id: act\nname: act\ndomains:\n  - https://act.example/\ntimeout: 10\nsearch:\n  actions:\n    - Click: button.show\n    - ScrollBottom\n    - Sleep: 500\n    - Eval: document.title\n    - Frame: iframe#list\n    - Wait: a.magnet\n  rows:\n    selector: a.magnet\n    title:\n      attr:\n    url:\n      attr: href\n",
+        )
+        .unwrap();
+        assert_eq!(define.search.actions.unwrap().len(), 6);
+
+        let result = parse(
+            "id: act\nname: act\ndomains:\n  - https://act.example/\ntimeout: 10\nprovider: Reqwest\nsearch:\n  actions:\n    - Click: \"a[\"\n  rows:\n    selector: a\n    title:\n      attr:\n    url:\n      attr: href\n",
+        );
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("only run by the Chrome provider"));
+        assert!(message.contains("search.actions[0]"));
+    }
+
+    #[test]
+    fn test_unknown_provider() {
+        let result = parse(
+            "id: bad\nname: bad\ndomains:\n  - https://bad.example/\ntimeout: 10\nprovider: Curl\nsearch:\n  rows:\n    selector: a\n    title:\n      attr:\n    url:\n      attr: href\n",
+        );
+
+        assert!(result.unwrap_err().to_string().contains("unknown variant"));
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        let result = parse(
+            "id: bad\nname: bad\ndomains:\n  - https://bad.example/\ntimeout: 10\nsearch:\n  rows:\n    selector: \"a[\"\n    title:\n      attr:\n    url:\n      attr: href\n",
+        );
+
+        assert!(result.is_err());
+    }
+}
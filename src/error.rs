@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::warn;
+use thiserror::Error;
+
+use crate::model::OperationResponse;
+use crate::resolver::ResolveError;
+
+pub type Result<T> = std::result::Result<T, AppError>;
+
+/// Errors of the http handlers, answered with an `OperationResponse` body.
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("database error: {0}")]
+    Db(#[from] rbatis::Error),
+    #[error("invalid request")]
+    Invalid(HashMap<String, String>),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    Resolve(#[from] ResolveError),
+    /// a feature that needs configuration, like the download client
+    #[error("{0}")]
+    Unavailable(&'static str),
+    /// Sonarr or a download client failed
+    #[error("{0}")]
+    Upstream(anyhow::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl AppError {
+    pub fn invalid(field: &str, msg: &str) -> Self {
+        let mut errors = HashMap::new();
+        errors.insert(field.to_string(), msg.to_string());
+        AppError::Invalid(errors)
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    /// Keeps database and resolver errors raised below `anyhow` apart.
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<rbatis::Error>() {
+            Ok(e) => return AppError::Db(e),
+            Err(e) => e,
+        };
+        match e.downcast::<ResolveError>() {
+            Ok(e) => AppError::Resolve(e),
+            Err(e) => AppError::Other(e),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            // sqlite answers `database is locked` while another writer holds it
+            AppError::Db(e) if e.to_string().contains("locked") => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Db(_) | AppError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Resolve(ResolveError::DiscoverFailure(_)) => StatusCode::BAD_GATEWAY,
+            AppError::Resolve(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            warn!("request failed: {}", self);
+        }
+        let body = match self {
+            AppError::Invalid(errors) => OperationResponse::invalid(errors.clone()),
+            _ => OperationResponse::failure(&self.to_string()),
+        };
+        HttpResponse::build(status).json(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::body::{Body, ResponseBody};
+
+    use super::*;
+
+    fn body(e: &AppError) -> serde_json::Value {
+        match e.error_response().body() {
+            ResponseBody::Body(Body::Bytes(bytes)) => serde_json::from_slice(bytes).unwrap(),
+            _ => panic!("no json body"),
+        }
+    }
+
+    #[test]
+    fn test_error_response() {
+        let e = AppError::NotFound("tv");
+        assert_eq!(e.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(body(&e)["msg"], "tv not found");
+        assert_eq!(body(&e)["status"], 1);
+
+        let e = AppError::invalid("url", "url must be a magnet link");
+        assert_eq!(e.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body(&e)["errors"]["url"], "url must be a magnet link");
+
+        let e = AppError::from(rbatis::Error::from("database is locked"));
+        assert_eq!(e.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let e = AppError::from(anyhow::Error::from(ResolveError::DefineNotFound(
+            "https://unknown.example".to_string(),
+        )));
+        assert!(matches!(e, AppError::Resolve(_)));
+        assert_eq!(e.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let e = AppError::from(anyhow::anyhow!("boom"));
+        assert_eq!(e.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
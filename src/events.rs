@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_std::task;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tinytemplate::TinyTemplate;
+
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+lazy_static! {
+    static ref NOTIFIERS: RwLock<Vec<NotifierDefine>> = RwLock::new(vec![]);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    #[serde(rename = "seed.discovered")]
+    SeedDiscovered,
+    #[serde(rename = "fetch.failed")]
+    FetchFailed,
+    #[serde(rename = "define.broken")]
+    DefineBroken,
+}
+
+impl EventKind {
+    fn template(&self) -> &'static str {
+        match self {
+            EventKind::SeedDiscovered => "{tv_name} EP{ep}: {seed_name}",
+            EventKind::FetchFailed => "{tv_name} fetch failed: {error}",
+            EventKind::DefineBroken => "define {define} found nothing on {url}",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            EventKind::SeedDiscovered => "seed.discovered",
+            EventKind::FetchFailed => "fetch.failed",
+            EventKind::DefineBroken => "define.broken",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    pub event: EventKind,
+    pub tv_id: Option<i64>,
+    pub tv_name: Option<String>,
+    pub ep: Option<i64>,
+    pub seed_name: Option<String>,
+    pub url: Option<String>,
+    pub define: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Event {
+    pub fn new(event: EventKind) -> Self {
+        Event {
+            event,
+            tv_id: None,
+            tv_name: None,
+            ep: None,
+            seed_name: None,
+            url: None,
+            define: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputDefine {
+    /// POSTs the event and message as JSON.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    Telegram {
+        token: String,
+        chat_id: String,
+        #[serde(default = "telegram_api")]
+        api: String,
+    },
+    /// GETs a url with `{title}` and `{message}` replaced, for Bark or ServerChan.
+    Url { url: String },
+}
+
+fn telegram_api() -> String {
+    TELEGRAM_API.to_string()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NotifierDefine {
+    #[serde(flatten)]
+    pub output: OutputDefine,
+    /// events sent to the output, all when empty
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    /// message template, with the event fields as variables
+    pub template: Option<String>,
+    #[serde(default)]
+    pub retries: u32,
+    /// milliseconds before the first retry, doubled on each retry
+    pub backoff: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct WebhookBody<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    title: String,
+    message: String,
+}
+
+/// Loads the notification outputs from a YAML list.
+pub fn init(file: Option<&str>) -> Result<()> {
+    let notifiers: Vec<NotifierDefine> = match file {
+        Some(file) => serde_yaml::from_str(&fs::read_to_string(file)?)?,
+        None => vec![],
+    };
+    for notifier in &notifiers {
+        render(notifier, &Event::new(EventKind::SeedDiscovered))?;
+    }
+    info!("notifier size:{}", notifiers.len());
+    *NOTIFIERS.write().unwrap() = notifiers;
+    Ok(())
+}
+
+/// Sends an event to every interested output in the background.
+pub fn emit(event: Event) {
+    let notifiers: Vec<NotifierDefine> = NOTIFIERS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|n| n.events.is_empty() || n.events.contains(&event.event))
+        .cloned()
+        .collect();
+    if notifiers.is_empty() {
+        return;
+    }
+
+    task::spawn(async move {
+        for notifier in notifiers {
+            if let Err(e) = send(&notifier, &event).await {
+                warn!("notify {} failed: {}", event.event.name(), e);
+            }
+        }
+    });
+}
+
+fn render(notifier: &NotifierDefine, event: &Event) -> Result<String> {
+    let template = notifier
+        .template
+        .as_deref()
+        .unwrap_or_else(|| event.event.template());
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&tinytemplate::format_unescaped);
+    tt.add_template("message", template)?;
+    Ok(tt.render("message", event)?)
+}
+
+pub async fn send(notifier: &NotifierDefine, event: &Event) -> Result<()> {
+    let title = format!("wkavu {}", event.event.name());
+    let message = render(notifier, event)?;
+
+    let mut backoff = Duration::from_millis(notifier.backoff.unwrap_or(1000));
+    let mut attempt = 0;
+    loop {
+        match post(&notifier.output, event, &title, &message).await {
+            Err(e) if attempt < notifier.retries => {
+                warn!("notify failed, retry in {:?}: {}", backoff, e);
+                task::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn post(output: &OutputDefine, event: &Event, title: &str, message: &str) -> Result<()> {
+    let client = reqwest::Client::new();
+    let req = match output {
+        OutputDefine::Webhook { url, headers } => {
+            let mut req = client.post(url).json(&WebhookBody {
+                event,
+                title: title.to_string(),
+                message: message.to_string(),
+            });
+            for (name, value) in headers {
+                req = req.header(name.as_str(), value.as_str());
+            }
+            req
+        }
+        OutputDefine::Telegram {
+            token,
+            chat_id,
+            api,
+        } => client
+            .post(format!(
+                "{}/bot{}/sendMessage",
+                api.trim_end_matches('/'),
+                token
+            ))
+            .json(&serde_json::json!({ "chat_id": chat_id, "text": message })),
+        OutputDefine::Url { url } => client.get(
+            url.replace("{title}", &urlencoding::encode(title))
+                .replace("{message}", &urlencoding::encode(message)),
+        ),
+    };
+    req.send().await?.error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    fn discovered() -> Event {
+        Event {
+            tv_id: Some(1),
+            tv_name: Some("三体".to_string()),
+            ep: Some(3),
+            seed_name: Some("三体 第03集".to_string()),
+            ..Event::new(EventKind::SeedDiscovered)
+        }
+    }
+
+    fn notifier(yaml: &str) -> NotifierDefine {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let n = notifier("type: url\nurl: http://localhost/{title}/{message}");
+        assert_eq!(render(&n, &discovered()).unwrap(), "三体 EP3: 三体 第03集");
+
+        let n = notifier("type: url\nurl: http://localhost\ntemplate: '{tv_name} <{error}>'");
+        assert_eq!(render(&n, &discovered()).unwrap(), "三体 <>");
+    }
+
+    #[async_std::test]
+    async fn test_webhook() {
+        let server = MockServer::start(|_| MockResponse::new(200, ""));
+        let n = notifier(&format!(
+            "type: webhook\nurl: {}/hook\nheaders:\n  X-Token: secret",
+            server.url
+        ));
+
+        send(&n, &discovered()).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].header("X-Token"), Some("secret"));
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["event"], "seed.discovered");
+        assert_eq!(body["tv_id"], 1);
+        assert_eq!(body["message"], "三体 EP3: 三体 第03集");
+    }
+
+    #[async_std::test]
+    async fn test_telegram_retries() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let server = MockServer::start(move |_| match counter.fetch_add(1, Ordering::SeqCst) {
+            0 => MockResponse::new(502, "Bad Gateway"),
+            _ => MockResponse::new(200, r#"{"ok":true}"#),
+        });
+        let n = notifier(&format!(
+            "type: telegram\ntoken: T0KEN\nchat_id: '42'\napi: {}\nretries: 1\nbackoff: 0",
+            server.url
+        ));
+
+        send(&n, &discovered()).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].path, "/botT0KEN/sendMessage");
+        let body: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(body["chat_id"], "42");
+    }
+
+    #[async_std::test]
+    async fn test_url() {
+        let server = MockServer::start(|_| MockResponse::new(500, ""));
+        let n = notifier(&format!(
+            "type: url\nurl: {}/KEY/{{title}}/{{message}}",
+            server.url
+        ));
+
+        assert!(send(&n, &discovered()).await.is_err());
+        assert_eq!(
+            server.requests()[0].path,
+            format!(
+                "/KEY/wkavu%20seed.discovered/{}",
+                urlencoding::encode("三体 EP3: 三体 第03集")
+            )
+        );
+    }
+}
@@ -1,12 +1,50 @@
 use std::collections::HashSet;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Mutex;
 
+use log::info;
+use rbatis::core::db::db_adapter::DBPool::Sqlite;
 use rbatis::rbatis::Rbatis;
 
 lazy_static! {
     pub static ref RB: Rbatis = Rbatis::new();
 }
 
+/// Links the database and applies pending migrations.
+pub async fn connect(url: &str) -> anyhow::Result<()> {
+    RB.link(url).await?;
+    if let Sqlite(pool, _) = RB.get_pool()? {
+        info!("Migration running...");
+        sqlx::migrate!("./migrations").run(pool).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+lazy_static! {
+    static ref TEST_DB: async_std::sync::Mutex<bool> = async_std::sync::Mutex::new(false);
+}
+
+/// Links the tests of this process to one fresh database file, named by the process id so
+/// parallel or repeated test runs don't share it.
+#[cfg(test)]
+pub async fn connect_test() {
+    let mut linked = TEST_DB.lock().await;
+    if !*linked {
+        let file = std::env::temp_dir().join(format!("wkavu-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        connect(&format!("sqlite://{}?mode=rwc", file.display()))
+            .await
+            .unwrap();
+        *linked = true;
+    }
+}
+
 lazy_static! {
     pub static ref WANT: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
 }
+
+pub const DEFAULT_FETCH_CONCURRENCY: usize = 4;
+
+/// Max shows fetched at the same time by a refresh or cron run.
+pub static FETCH_CONCURRENCY: AtomicUsize = AtomicUsize::new(DEFAULT_FETCH_CONCURRENCY);
@@ -1,22 +1,39 @@
 use actix_web::http::header;
 use actix_web::http::header::ContentType;
-use actix_web::{web, HttpRequest, HttpResponse};
 
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 
-use log::info;
+use log::{info, warn};
 
 use rbatis::crud::CRUD;
 use rbatis::{Page, PageRequest};
 
+use crate::cache;
+use crate::define;
+use crate::downloader::{self, PushOptions};
+use crate::error::{AppError, Result};
 use crate::global;
-use crate::model::{OperationResponse, PageResponse, Tv, TvSeed};
-use crate::resolver::Resolver;
+use crate::library::{self, ExportOptions, Format};
+use crate::link;
+use crate::model::{
+    DataResponse, OperationResponse, PageResponse, Tv, TvResponse, TvSeed, TvSeedOverride,
+};
+use crate::openapi;
+use crate::overrides;
+use crate::resolver::{self, Resolver};
+use crate::server;
+use crate::sonarr;
 use crate::torznab::TorznabProvider;
+use crate::trackers;
+use crate::tvs::{escape_like, find_tv, non_empty, remove_tv, save_tv, tv_filter};
 use async_std::task;
 
 pub async fn root() -> HttpResponse {
     HttpResponse::Found()
-        .header(header::LOCATION, "index.html")
+        .header(
+            header::LOCATION,
+            format!("{}/index.html", server::base_path()),
+        )
         .finish()
 }
 
@@ -24,45 +41,139 @@ pub async fn health() -> HttpResponse {
     HttpResponse::Ok().body("server is up!")
 }
 
-#[derive(serde::Deserialize, Debug)]
+pub async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(openapi::get())
+}
+
+#[derive(serde::Deserialize, Debug, schemars::JsonSchema)]
 pub struct ApiRequest {
     pub(crate) t: String,
-    q: Option<String>,
+    pub(crate) q: Option<String>,
     pub(crate) tvdbid: Option<String>,
-    season: Option<i32>,
+    pub(crate) season: Option<i32>,
     pub(crate) ep: Option<i32>,
     pub(crate) offset: Option<u64>,
     pub(crate) limit: Option<u64>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, schemars::JsonSchema)]
 pub struct TvsRequest {
     perPage: Option<u64>,
     page: Option<u64>,
+    /// matches name, tvname, aliases or the exact tvdbid
+    keyword: Option<String>,
+    orderBy: Option<String>,
+    orderDir: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, schemars::JsonSchema)]
 pub struct SeedsRequest {
     perPage: Option<u64>,
     page: Option<u64>,
+    keyword: Option<String>,
+    season: Option<i64>,
+    ep: Option<i64>,
+    /// matched against the seed name, e.g. `1080p`
+    quality: Option<String>,
+    /// `pinned`, `manual` or `scraped`
+    status: Option<String>,
+    orderBy: Option<String>,
+    orderDir: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+const TV_SORTS: &[&str] = &["id", "name", "tvname", "tvdbid", "updated_at"];
+const SEED_SORTS: &[&str] = &["id", "season", "ep", "name", "size", "pinned"];
+
+/// Picks an allowed sort column and direction, falling back to `default` ascending.
+fn sort<'a>(
+    order_by: Option<&str>,
+    order_dir: Option<&str>,
+    allowed: &[&'a str],
+    default: &'a str,
+) -> (&'a str, &'static str) {
+    let column = order_by
+        .and_then(|order_by| allowed.iter().find(|column| **column == order_by))
+        .copied()
+        .unwrap_or(default);
+    let dir = match order_dir {
+        Some(dir) if dir.eq_ignore_ascii_case("desc") => "desc",
+        _ => "asc",
+    };
+    (column, dir)
+}
+
+#[derive(serde::Deserialize, Debug, schemars::JsonSchema)]
 pub struct SeedsPathRequest {
     tvid: u64,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct DownloadPathRequest {
+    seed_id: i64,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct SeedPathRequest {
+    seed_id: i64,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct OverridePathRequest {
+    id: i64,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct SeedPatchRequest {
+    season: Option<i64>,
+    ep: Option<i64>,
+    pinned: Option<bool>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct SeedAddRequest {
+    url: String,
+    name: Option<String>,
+    ep: i64,
+    season: Option<i64>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct ExportRequest {
+    format: Option<String>,
+    seeds: Option<bool>,
+    overrides: Option<bool>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct ImportRequest {
+    format: Option<String>,
+    dryRun: Option<bool>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct SonarrSyncRequest {
+    dryRun: Option<bool>,
+    /// creates shows for new series, defaults to `--sonarr-auto-create`
+    create: Option<bool>,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
 pub struct TvDeleteRequest {
     id: u64,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct TvPathRequest {
+    id: i64,
+}
+
+#[derive(serde::Deserialize, schemars::JsonSchema)]
 pub struct TvAddRequest {
     name: String,
     tvdbid: String,
     tvname: String,
-    url: String,
+    url: Option<String>,
+    aliases: Option<String>,
 }
 
 pub async fn api(info: web::Query<ApiRequest>, req: HttpRequest) -> HttpResponse {
@@ -77,83 +188,538 @@ pub async fn api(info: web::Query<ApiRequest>, req: HttpRequest) -> HttpResponse
     let mut builder = HttpResponse::Ok();
     builder.set(ContentType::xml());
 
-    let result = provider.handle(&info).await;
+    let base_url = {
+        let connection_info = req.connection_info();
+        format!(
+            "{}://{}{}",
+            connection_info.scheme(),
+            connection_info.host(),
+            server::base_path()
+        )
+    };
+    match provider.handle(&info, &base_url).await {
+        Ok(result) => builder.body(result),
+        Err(e) => {
+            // Sonarr reads the error element, not the json the admin api answers
+            warn!("torznab query failed: {}", e);
+            HttpResponse::build(e.status_code())
+                .set(ContentType::xml())
+                .body(provider.error(900, &e.to_string()))
+        }
+    }
+}
+
+/// A seed with a url and the magnet of it with the trackers of its show.
+async fn find_magnet(seed_id: i64) -> Result<String> {
+    let seed = find_seed(seed_id)
+        .await?
+        .filter(|seed| seed.url.is_some())
+        .ok_or(AppError::NotFound("seed"))?;
+    let wrapper = global::RB.new_wrapper().eq(Tv::id(), seed.tv_id);
+    let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await?;
+    Ok(trackers::render(tv.as_ref(), seed.url.as_ref().unwrap()))
+}
+
+/// Serves the cached `.torrent` of a seed, or redirects to its magnet.
+pub async fn download(path: web::Path<DownloadPathRequest>) -> Result<HttpResponse> {
+    let url = find_magnet(path.seed_id).await?;
 
-    builder.body(result)
+    Ok(
+        match link::info_hash(&url).and_then(|info_hash| cache::load(&info_hash)) {
+            Some(torrent) => HttpResponse::Ok()
+                .content_type("application/x-bittorrent")
+                .header(
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.torrent\"", path.seed_id),
+                )
+                .body(torrent),
+            None => HttpResponse::Found().header(header::LOCATION, url).finish(),
+        },
+    )
 }
 
-pub async fn refresh() -> HttpResponse {
+pub async fn refresh() -> Result<HttpResponse> {
+    let tvs: Vec<Tv> = global::RB.fetch_list().await?;
     task::spawn(async {
-        let tvs: Vec<Tv> = global::RB.fetch_list().await.unwrap();
         let resolver = Resolver::new();
-        for tv in tvs {
-            resolver.fetch_by_tv(tv.id.unwrap()).await;
-        }
+        resolver
+            .fetch_all(tvs.into_iter().filter_map(|tv| tv.id).collect())
+            .await;
     });
-    HttpResponse::Ok().json(OperationResponse::success())
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+pub async fn define_reload() -> HttpResponse {
+    let size = define::reload();
+    HttpResponse::Ok().json(OperationResponse {
+        status: 0,
+        msg: format!("{} defines loaded", size),
+        ..Default::default()
+    })
 }
 
-pub async fn tv_list(tvs_request: web::Query<TvsRequest>) -> HttpResponse {
-    let wrapper = global::RB.new_wrapper();
+pub async fn tv_list(tvs_request: web::Query<TvsRequest>) -> Result<HttpResponse> {
+    let mut wrapper = tv_filter(tvs_request.keyword.clone());
+    let (order_by, order_dir) = sort(
+        tvs_request.orderBy.as_deref(),
+        tvs_request.orderDir.as_deref(),
+        TV_SORTS,
+        Tv::id(),
+    );
+    wrapper = wrapper.order_by(order_dir == "asc", &[order_by]);
+
     let page = PageRequest::new(
         tvs_request.page.unwrap_or(1_u64),
         tvs_request.perPage.unwrap_or(10_u64),
     );
-    let tv_page: Page<Tv> = global::RB
-        .fetch_page_by_wrapper(wrapper, &page)
-        .await
-        .unwrap();
+    let tv_page: Page<Tv> = global::RB.fetch_page_by_wrapper(wrapper, &page).await?;
 
-    let response = PageResponse::from(tv_page);
-    HttpResponse::Ok().json(response)
+    let response = PageResponse::from(tv_page).sorted(order_by, order_dir);
+    Ok(HttpResponse::Ok().json(response))
 }
 
 pub async fn seed_list(
     seeds_path_request: web::Path<SeedsPathRequest>,
     seeds_request: web::Query<SeedsRequest>,
-) -> HttpResponse {
-    let wrapper = global::RB
+) -> Result<HttpResponse> {
+    let mut wrapper = global::RB
         .new_wrapper()
         .eq(TvSeed::tv_id(), seeds_path_request.tvid);
+    if let Some(season) = seeds_request.season {
+        wrapper = wrapper.and().eq(TvSeed::season(), season);
+    }
+    if let Some(ep) = seeds_request.ep {
+        wrapper = wrapper.and().eq(TvSeed::ep(), ep);
+    }
+    // `%` and `_` in the keyword or quality match themselves
+    for part in [&seeds_request.keyword, &seeds_request.quality] {
+        if let Some(part) = non_empty(part.clone()) {
+            wrapper = wrapper
+                .and()
+                .push_sql("name LIKE ? ESCAPE '\\'")
+                .push_arg(format!("%{}%", escape_like(&part)));
+        }
+    }
+    match seeds_request.status.as_deref() {
+        Some("pinned") => wrapper = wrapper.and().eq(TvSeed::pinned(), true),
+        Some("manual") => wrapper = wrapper.and().eq(TvSeed::manual(), true),
+        Some("scraped") => wrapper = wrapper.and().is_null(TvSeed::manual()),
+        _ => {}
+    }
+
+    // pinned seeds first unless another sort is asked for
+    let (order_by, order_dir) = match seeds_request.orderBy {
+        Some(_) => sort(
+            seeds_request.orderBy.as_deref(),
+            seeds_request.orderDir.as_deref(),
+            SEED_SORTS,
+            TvSeed::id(),
+        ),
+        None => (TvSeed::pinned(), "desc"),
+    };
+    wrapper = wrapper.order_by(order_dir == "asc", &[order_by]);
+
     let page = PageRequest::new(
         seeds_request.page.unwrap_or(1_u64),
         seeds_request.perPage.unwrap_or(10_u64),
     );
-    let seed_page: Page<TvSeed> = global::RB
-        .fetch_page_by_wrapper(wrapper, &page)
-        .await
-        .unwrap();
+    let seed_page: Page<TvSeed> = global::RB.fetch_page_by_wrapper(wrapper, &page).await?;
+
+    let response = PageResponse::from(seed_page).sorted(order_by, order_dir);
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    let response = PageResponse::from(seed_page);
-    HttpResponse::Ok().json(response)
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub struct TvPatchRequest {
+    name: Option<String>,
+    tvdbid: Option<String>,
+    tvname: Option<String>,
+    url: Option<String>,
+    aliases: Option<String>,
 }
 
-pub async fn tv_add(tv_add: web::Json<TvAddRequest>) -> HttpResponse {
+pub async fn tv_add(tv_add: web::Json<TvAddRequest>) -> Result<HttpResponse> {
+    let tv_add = tv_add.into_inner();
     let new_tv = Tv {
         id: None,
-        tvdbid: Some(tv_add.tvdbid.to_string()),
-        tvname: Some(tv_add.tvname.to_string()),
-        url: Some(tv_add.url.to_string()),
-        name: Some(tv_add.name.to_string()),
+        tvdbid: Some(tv_add.tvdbid.trim().to_string()),
+        tvname: Some(tv_add.tvname),
+        url: non_empty(tv_add.url),
+        name: Some(tv_add.name),
+        aliases: tv_add.aliases,
+        updated_at: None,
+        sonarr_id: None,
+        removed: None,
     };
 
-    global::RB.save(&new_tv, &[]).await.unwrap();
+    save_tv(new_tv).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+pub async fn tv_get(path: web::Path<TvPathRequest>) -> Result<HttpResponse> {
+    let tv = find_tv(path.id).await?;
+    Ok(HttpResponse::Ok().json(DataResponse::new(TvResponse::from(tv))))
+}
+
+pub async fn tv_update(
+    path: web::Path<TvPathRequest>,
+    tv_update: web::Json<TvAddRequest>,
+) -> Result<HttpResponse> {
+    let tv = find_tv(path.id).await?;
+
+    let tv_update = tv_update.into_inner();
+    save_tv(Tv {
+        id: tv.id,
+        tvdbid: Some(tv_update.tvdbid.trim().to_string()),
+        tvname: Some(tv_update.tvname),
+        url: non_empty(tv_update.url),
+        name: Some(tv_update.name),
+        aliases: tv_update.aliases,
+        updated_at: tv.updated_at,
+        sonarr_id: tv.sonarr_id,
+        removed: tv.removed,
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+pub async fn tv_patch(
+    path: web::Path<TvPathRequest>,
+    tv_patch: web::Json<TvPatchRequest>,
+) -> Result<HttpResponse> {
+    let mut tv = find_tv(path.id).await?;
 
-    HttpResponse::Ok().json(OperationResponse::success())
+    let tv_patch = tv_patch.into_inner();
+    if let Some(name) = tv_patch.name {
+        tv.name = Some(name);
+    }
+    if let Some(tvdbid) = tv_patch.tvdbid {
+        tv.tvdbid = Some(tvdbid.trim().to_string());
+    }
+    if let Some(tvname) = tv_patch.tvname {
+        tv.tvname = Some(tvname);
+    }
+    if tv_patch.url.is_some() {
+        tv.url = non_empty(tv_patch.url);
+    }
+    if tv_patch.aliases.is_some() {
+        tv.aliases = tv_patch.aliases;
+    }
+    save_tv(tv).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
 }
 
-pub async fn tv_delete(tv_delete_request: web::Json<TvDeleteRequest>) -> HttpResponse {
-    let wrapper = global::RB.new_wrapper().eq(Tv::id(), tv_delete_request.id);
-    global::RB.remove_by_wrapper::<Tv>(wrapper).await.unwrap();
-    HttpResponse::Ok().json(OperationResponse::success())
+pub async fn tv_remove(path: web::Path<TvPathRequest>) -> Result<HttpResponse> {
+    remove_tv(path.id).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+/// Same as `DELETE /admin/tvs/{id}`, kept for older clients.
+pub async fn tv_delete(tv_delete_request: web::Json<TvDeleteRequest>) -> Result<HttpResponse> {
+    remove_tv(tv_delete_request.id).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+async fn find_seed(id: i64) -> Result<Option<TvSeed>> {
+    let wrapper = global::RB.new_wrapper().eq(TvSeed::id(), id);
+    Ok(global::RB.fetch_by_wrapper(wrapper).await?)
+}
+
+async fn get_seed(id: i64) -> Result<TvSeed> {
+    find_seed(id).await?.ok_or(AppError::NotFound("seed"))
+}
+
+/// Changes the season, episode or pin of a seed, kept across scrapes by info hash.
+pub async fn seed_update(
+    path: web::Path<SeedPathRequest>,
+    seed_patch: web::Json<SeedPatchRequest>,
+) -> Result<HttpResponse> {
+    let mut seed = get_seed(path.seed_id).await?;
+    // without an override the next scrape would undo the change
+    let info_hash = seed
+        .info_hash
+        .clone()
+        .or_else(|| seed.url.as_deref().and_then(link::info_hash))
+        .ok_or_else(|| AppError::BadRequest("seed has no info hash".to_string()))?;
+
+    if seed_patch.season.is_some() {
+        seed.season = seed_patch.season;
+    }
+    if seed_patch.ep.is_some() {
+        seed.ep = seed_patch.ep;
+    }
+    if seed_patch.pinned.is_some() {
+        seed.pinned = seed_patch.pinned;
+    }
+    if seed_patch.season.is_some() || seed_patch.ep.is_some() {
+        let tv = find_tv(seed.tv_id.unwrap_or_default()).await?;
+        let tvname = resolver::series_name(&tv);
+        resolver::rename(tvname, &mut seed);
+    }
+    global::RB.update_by_column(TvSeed::id(), &seed).await?;
+
+    overrides::save(seed.tv_id.unwrap(), &info_hash, |o| {
+        if seed_patch.season.is_some() {
+            o.season = seed_patch.season;
+        }
+        if seed_patch.ep.is_some() {
+            o.ep = seed_patch.ep;
+        }
+        if seed_patch.pinned.is_some() {
+            o.pinned = seed_patch.pinned;
+        }
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+/// Deletes a seed and keeps its info hash out of later scrapes.
+pub async fn seed_block(path: web::Path<SeedPathRequest>) -> Result<HttpResponse> {
+    let seed = get_seed(path.seed_id).await?;
+    let info_hash = seed
+        .info_hash
+        .clone()
+        .or_else(|| seed.url.as_deref().and_then(link::info_hash))
+        .ok_or_else(|| AppError::BadRequest("seed has no info hash".to_string()))?;
+
+    overrides::save(seed.tv_id.unwrap(), &info_hash, |o| o.blocked = Some(true)).await?;
+    let wrapper = global::RB.new_wrapper().eq(TvSeed::id(), path.seed_id);
+    global::RB.remove_by_wrapper::<TvSeed>(wrapper).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+pub async fn seed_remove(path: web::Path<SeedPathRequest>) -> Result<HttpResponse> {
+    get_seed(path.seed_id).await?;
+    let wrapper = global::RB.new_wrapper().eq(TvSeed::id(), path.seed_id);
+    global::RB.remove_by_wrapper::<TvSeed>(wrapper).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+/// Adds a magnet to a show by hand.
+pub async fn seed_add(
+    path: web::Path<TvPathRequest>,
+    seed_add: web::Json<SeedAddRequest>,
+) -> Result<HttpResponse> {
+    let tv = find_tv(path.id).await?;
+
+    let seed_add = seed_add.into_inner();
+    let url = seed_add.url.trim().to_string();
+    let info_hash = match link::info_hash(&url) {
+        Some(info_hash) if url.starts_with("magnet:") => info_hash,
+        _ => return Err(AppError::invalid("url", "url must be a magnet link")),
+    };
+
+    let mut seed = TvSeed {
+        id: None,
+        tv_id: Some(path.id),
+        ep: Some(seed_add.ep),
+        name: Some(non_empty(seed_add.name).unwrap_or_else(|| info_hash.clone())),
+        url: Some(url),
+        size: None,
+        info_hash: Some(info_hash),
+        season: seed_add.season,
+        pinned: None,
+        manual: Some(true),
+    };
+    // the title has to name the episode for Sonarr to grab the seed
+    resolver::rename(resolver::series_name(&tv), &mut seed);
+    global::RB.save(&seed, &[]).await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+pub async fn override_list(path: web::Path<TvPathRequest>) -> Result<HttpResponse> {
+    let overrides = overrides::load(path.id).await?;
+    Ok(HttpResponse::Ok().json(DataResponse::new(overrides)))
+}
+
+/// Deletes an override, unblocking its info hash from the next scrape.
+pub async fn override_delete(path: web::Path<OverridePathRequest>) -> Result<HttpResponse> {
+    let wrapper = global::RB.new_wrapper().eq(TvSeedOverride::id(), path.id);
+    global::RB
+        .remove_by_wrapper::<TvSeedOverride>(wrapper)
+        .await?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
+}
+
+fn parse_format(format: Option<&str>) -> Result<Format> {
+    format
+        .unwrap_or("json")
+        .parse::<Format>()
+        .map_err(|e| AppError::BadRequest(e.to_string()))
+}
+
+/// Downloads the show library, with seeds and overrides when asked.
+pub async fn library_export(export_request: web::Query<ExportRequest>) -> Result<HttpResponse> {
+    let format = parse_format(export_request.format.as_deref())?;
+    let options = ExportOptions {
+        seeds: export_request.seeds.unwrap_or(false),
+        overrides: export_request.overrides.unwrap_or(false),
+    };
+    let library = library::export(&options).await?;
+    let content = library::render(&library, format)?;
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"wkavu.{}\"", format.extension()),
+        )
+        .body(content))
+}
+
+/// Merges an uploaded library by tvdbid, only reporting changes on `dryRun=true`.
+pub async fn library_import(
+    import_request: web::Query<ImportRequest>,
+    body: String,
+) -> Result<HttpResponse> {
+    let format = parse_format(import_request.format.as_deref())?;
+    let library = library::parse(&body, format).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let report = library::import(&library, import_request.dryRun.unwrap_or(false)).await?;
+    Ok(HttpResponse::Ok().json(DataResponse::new(report)))
+}
+
+/// Syncs shows with the monitored series of Sonarr.
+pub async fn sonarr_sync(sync_request: web::Query<SonarrSyncRequest>) -> Result<HttpResponse> {
+    let config = sonarr::config().ok_or(AppError::Unavailable("sonarr is not configured"))?;
+    let create = sync_request.create.unwrap_or(config.auto_create);
+    let report = sonarr::sync(&config, create, sync_request.dryRun.unwrap_or(false))
+        .await
+        .map_err(|e| match AppError::from(e) {
+            AppError::Other(e) => AppError::Upstream(e),
+            e => e,
+        })?;
+    Ok(HttpResponse::Ok().json(DataResponse::new(report)))
+}
+
+pub async fn seed_push(
+    path: web::Path<DownloadPathRequest>,
+    options: web::Json<PushOptions>,
+) -> Result<HttpResponse> {
+    let client = downloader::get().ok_or(AppError::Unavailable("no download client configured"))?;
+    let magnet = find_magnet(path.seed_id).await?;
+
+    client
+        .add(&magnet, &options)
+        .await
+        .map_err(AppError::Upstream)?;
+    Ok(HttpResponse::Ok().json(OperationResponse::success()))
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{http, test};
+    use actix_web::{http, test, App};
 
     use super::*;
 
+    #[actix_rt::test]
+    async fn test_seed_list_keyword() {
+        global::connect_test().await;
+        save_tv(Tv {
+            id: None,
+            tvdbid: Some("90043".to_string()),
+            tvname: Some("Percent Show".to_string()),
+            url: None,
+            name: Some("百分剧".to_string()),
+            aliases: None,
+            updated_at: None,
+            sonarr_id: None,
+            removed: None,
+        })
+        .await
+        .unwrap();
+        let wrapper = global::RB.new_wrapper().eq(Tv::tvdbid(), "90043");
+        let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await.unwrap();
+        let tv_id = tv.unwrap().id.unwrap();
+        for (ep, name) in [
+            (1, "百分剧 100% 1080p"),
+            (2, "百分剧 1000 1080p"),
+            (3, "百分剧 1_0 720p"),
+        ] {
+            let seed = TvSeed {
+                id: None,
+                tv_id: Some(tv_id),
+                ep: Some(ep),
+                url: Some(format!("magnet:?xt=urn:btih:{:040}", ep)),
+                name: Some(name.to_string()),
+                size: None,
+                info_hash: Some(format!("{:040}", ep)),
+                season: None,
+                pinned: None,
+                manual: None,
+            };
+            global::RB.save(&seed, &[]).await.unwrap();
+        }
+
+        let mut app = test::init_service(
+            App::new().route("/admin/seeds/tvid/{tvid}", web::get().to(seed_list)),
+        )
+        .await;
+        for (query, expected) in [
+            ("keyword=100%25", vec![1]),
+            ("keyword=1_0", vec![3]),
+            ("keyword=100&quality=720p", Vec::<i64>::new()),
+            ("quality=1080p", vec![1, 2]),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!(
+                    "/admin/seeds/tvid/{}?{}&orderBy=ep&orderDir=asc",
+                    tv_id, query
+                ))
+                .to_request();
+            let resp = test::call_service(&mut app, req).await;
+            let body: serde_json::Value = test::read_body_json(resp).await;
+            let eps: Vec<i64> = body["data"]["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|seed| seed["ep"].as_i64().unwrap())
+                .collect();
+            assert_eq!(eps, expected, "{}", query);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_seed_add_title() {
+        global::connect_test().await;
+        save_tv(Tv {
+            id: None,
+            tvdbid: Some("90042".to_string()),
+            tvname: Some("Manual Show".to_string()),
+            url: None,
+            name: Some("手动剧".to_string()),
+            aliases: None,
+            updated_at: None,
+            sonarr_id: None,
+            removed: None,
+        })
+        .await
+        .unwrap();
+        let wrapper = global::RB.new_wrapper().eq(Tv::tvdbid(), "90042");
+        let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await.unwrap();
+        let tv_id = tv.unwrap().id.unwrap();
+
+        let mut app =
+            test::init_service(App::new().route("/admin/tvs/{id}/seeds", web::post().to(seed_add)))
+                .await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/tvs/{}/seeds", tv_id))
+            .set_json(&serde_json::json!({
+                "url": "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567",
+                "name": "手动剧 第05集",
+                "ep": 5,
+                "season": 2
+            }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), tv_id);
+        let seeds: Vec<TvSeed> = global::RB.fetch_list_by_wrapper(wrapper).await.unwrap();
+        assert_eq!(
+            seeds[0].name.as_deref(),
+            Some("Manual Show S02E5 - 5 - [chinese] - 手动剧 第05集 - Wkavu")
+        );
+    }
+
     #[actix_rt::test]
     async fn test_health_ok() {
         let _req =
@@ -162,4 +728,17 @@ mod tests {
         let resp = health().await;
         assert_eq!(resp.status(), http::StatusCode::OK);
     }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(sort(None, None, TV_SORTS, "id"), ("id", "asc"));
+        assert_eq!(
+            sort(Some("updated_at"), Some("DESC"), TV_SORTS, "id"),
+            ("updated_at", "desc")
+        );
+        assert_eq!(
+            sort(Some("id; DROP TABLE tv"), Some("up"), TV_SORTS, "id"),
+            ("id", "asc")
+        );
+    }
 }
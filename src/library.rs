@@ -0,0 +1,557 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use rbatis::crud::CRUD;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::global;
+use crate::link;
+use crate::model::{self, Tv, TvSeed};
+use crate::overrides;
+use crate::tvs::{check_tv, non_empty};
+
+const VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum LibraryError {
+    #[error("Unknown library format: {0}, use json, yaml or csv")]
+    UnknownFormat(String),
+    #[error("CSV only holds shows, use json or yaml to include seeds and overrides")]
+    CsvNested,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = LibraryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "csv" => Ok(Format::Csv),
+            _ => Err(LibraryError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format from a file extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.parse().ok())
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Yaml => "application/x-yaml",
+            Format::Csv => "text/csv",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Yaml => "yaml",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, schemars::JsonSchema)]
+pub struct Library {
+    #[serde(default)]
+    pub version: u32,
+    pub tvs: Vec<LibraryTv>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, schemars::JsonSchema)]
+pub struct LibraryTv {
+    pub tvdbid: String,
+    pub name: Option<String>,
+    pub tvname: Option<String>,
+    pub url: Option<String>,
+    pub aliases: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub seeds: Vec<LibrarySeed>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<LibraryOverride>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, schemars::JsonSchema)]
+pub struct LibrarySeed {
+    pub url: String,
+    pub name: Option<String>,
+    pub season: Option<i64>,
+    pub ep: Option<i64>,
+    pub size: Option<i64>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub manual: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, schemars::JsonSchema)]
+pub struct LibraryOverride {
+    pub info_hash: String,
+    #[serde(default)]
+    pub blocked: bool,
+    pub season: Option<i64>,
+    pub ep: Option<i64>,
+    pub pinned: Option<bool>,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportOptions {
+    pub seeds: bool,
+    pub overrides: bool,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    Unchanged,
+    Invalid,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, schemars::JsonSchema)]
+pub struct Change {
+    pub tvdbid: String,
+    pub name: String,
+    pub action: Action,
+    /// changed fields for updates, problems for invalid shows
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Default, schemars::JsonSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub invalid: usize,
+    pub seeds: usize,
+    pub overrides: usize,
+    pub changes: Vec<Change>,
+}
+
+pub async fn export(options: &ExportOptions) -> Result<Library> {
+    let tvs: Vec<Tv> = global::RB.fetch_list().await?;
+    let mut library = Library {
+        version: VERSION,
+        tvs: vec![],
+    };
+
+    for tv in tvs {
+        let tv_id = tv.id.unwrap();
+        let mut item = LibraryTv {
+            tvdbid: tv.tvdbid.unwrap_or_default(),
+            name: tv.name,
+            tvname: tv.tvname,
+            url: tv.url,
+            aliases: tv.aliases,
+            seeds: vec![],
+            overrides: vec![],
+        };
+
+        if options.seeds {
+            let wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), tv_id);
+            let seeds: Vec<TvSeed> = global::RB.fetch_list_by_wrapper(wrapper).await?;
+            item.seeds = seeds
+                .into_iter()
+                .filter(|seed| seed.url.is_some())
+                .map(|seed| LibrarySeed {
+                    url: seed.url.unwrap(),
+                    name: seed.name,
+                    season: seed.season,
+                    ep: seed.ep,
+                    size: seed.size,
+                    pinned: seed.pinned.unwrap_or(false),
+                    manual: seed.manual.unwrap_or(false),
+                })
+                .collect();
+        }
+        if options.overrides {
+            item.overrides = overrides::load(tv_id)
+                .await?
+                .into_iter()
+                .filter(|o| o.info_hash.is_some())
+                .map(|o| LibraryOverride {
+                    info_hash: o.info_hash.unwrap(),
+                    blocked: o.blocked.unwrap_or(false),
+                    season: o.season,
+                    ep: o.ep,
+                    pinned: o.pinned,
+                })
+                .collect();
+        }
+        library.tvs.push(item);
+    }
+    Ok(library)
+}
+
+pub fn render(library: &Library, format: Format) -> Result<String> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(library)? + "\n"),
+        Format::Yaml => Ok(serde_yaml::to_string(library)?),
+        Format::Csv => {
+            let nested = library
+                .tvs
+                .iter()
+                .any(|tv| !tv.seeds.is_empty() || !tv.overrides.is_empty());
+            if nested {
+                return Err(LibraryError::CsvNested.into());
+            }
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for tv in &library.tvs {
+                writer.serialize(tv)?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+    }
+}
+
+pub fn parse(content: &str, format: Format) -> Result<Library> {
+    match format {
+        Format::Json => Ok(serde_json::from_str(content)?),
+        Format::Yaml => Ok(serde_yaml::from_str(content)?),
+        Format::Csv => {
+            let mut reader = csv::Reader::from_reader(content.as_bytes());
+            let tvs = reader
+                .deserialize()
+                .collect::<std::result::Result<Vec<LibraryTv>, csv::Error>>()?;
+            Ok(Library {
+                version: VERSION,
+                tvs,
+            })
+        }
+    }
+}
+
+/// Replaces the fields of a show that are set in the imported one.
+fn merge(tv: &Tv, incoming: &LibraryTv) -> (Tv, Vec<String>) {
+    let mut merged = tv.clone();
+    let mut fields = vec![];
+    let updates = [
+        ("name", &mut merged.name, &incoming.name),
+        ("tvname", &mut merged.tvname, &incoming.tvname),
+        ("url", &mut merged.url, &incoming.url),
+        ("aliases", &mut merged.aliases, &incoming.aliases),
+    ];
+    for (field, value, new_value) in updates {
+        let new_value = new_value.as_ref().filter(|v| !v.trim().is_empty());
+        if new_value.is_some() && value.as_ref() != new_value {
+            *value = new_value.cloned();
+            fields.push(field.to_string());
+        }
+    }
+    // shows saved without a series name get the display name, like new ones
+    if non_empty(merged.tvname.clone()).is_none() && merged.name != merged.tvname {
+        merged.tvname = merged.name.clone();
+        fields.push("tvname".to_string());
+    }
+    (merged, fields)
+}
+
+fn to_tv(incoming: &LibraryTv) -> Tv {
+    Tv {
+        id: None,
+        tvdbid: Some(incoming.tvdbid.trim().to_string()),
+        tvname: non_empty(incoming.tvname.clone()).or_else(|| incoming.name.clone()),
+        url: incoming.url.clone().filter(|url| !url.trim().is_empty()),
+        name: incoming.name.clone(),
+        aliases: incoming.aliases.clone(),
+        updated_at: None,
+        sonarr_id: None,
+        removed: None,
+    }
+}
+
+/// Matches imported shows with existing ones by tvdbid.
+pub fn plan(existing: &[Tv], incoming: &[LibraryTv]) -> Vec<Change> {
+    incoming
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let tvdbid = item.tvdbid.trim().to_string();
+            let current = existing
+                .iter()
+                .find(|tv| tv.tvdbid.as_deref() == Some(tvdbid.as_str()));
+            let (tv, mut fields) = match current {
+                Some(tv) => merge(tv, item),
+                None => (to_tv(item), vec![]),
+            };
+
+            let mut problems: Vec<String> = check_tv(&tv)
+                .into_iter()
+                .map(|(field, message)| format!("{}: {}", field, message))
+                .collect();
+            let duplicated = incoming[..i]
+                .iter()
+                .any(|other| other.tvdbid.trim() == tvdbid);
+            if duplicated {
+                problems.push(format!("tvdbid: {} appears more than once", tvdbid));
+            }
+            problems.sort();
+
+            let action = if !problems.is_empty() {
+                fields = problems;
+                Action::Invalid
+            } else if current.is_none() {
+                Action::Create
+            } else if fields.is_empty() {
+                Action::Unchanged
+            } else {
+                Action::Update
+            };
+            Change {
+                tvdbid,
+                name: tv.name.unwrap_or_default(),
+                action,
+                fields,
+            }
+        })
+        .collect()
+}
+
+/// Imports shows merged by tvdbid, reporting changes without saving on a dry run.
+pub async fn import(library: &Library, dry_run: bool) -> Result<ImportReport> {
+    let existing: Vec<Tv> = global::RB.fetch_list().await?;
+    let changes = plan(&existing, &library.tvs);
+    let mut report = ImportReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    for (change, item) in changes.iter().zip(&library.tvs) {
+        let current = existing
+            .iter()
+            .find(|tv| tv.tvdbid.as_deref() == Some(change.tvdbid.as_str()));
+        let mut tv_id = current.and_then(|tv| tv.id);
+
+        match change.action {
+            Action::Invalid => {
+                report.invalid += 1;
+                continue;
+            }
+            Action::Unchanged => report.unchanged += 1,
+            Action::Create => {
+                report.created += 1;
+                if !dry_run {
+                    let mut tv = to_tv(item);
+                    tv.updated_at = Some(model::timestamp());
+                    tv_id = global::RB.save(&tv, &[]).await?.last_insert_id;
+                }
+            }
+            Action::Update => {
+                report.updated += 1;
+                if !dry_run {
+                    let (mut tv, _) = merge(current.unwrap(), item);
+                    tv.updated_at = Some(model::timestamp());
+                    global::RB.update_by_column(Tv::id(), &tv).await?;
+                }
+            }
+        }
+
+        report.seeds += import_seeds(tv_id, &item.seeds, dry_run).await?;
+        report.overrides += item.overrides.len();
+        if let (false, Some(tv_id)) = (dry_run, tv_id) {
+            for o in &item.overrides {
+                overrides::save(tv_id, &o.info_hash.to_lowercase(), |saved| {
+                    saved.blocked = Some(o.blocked);
+                    saved.season = o.season;
+                    saved.ep = o.ep;
+                    saved.pinned = o.pinned;
+                })
+                .await?;
+            }
+        }
+    }
+
+    report.changes = changes;
+    Ok(report)
+}
+
+/// Adds the seeds a show doesn't have yet, by info hash.
+async fn import_seeds(tv_id: Option<i64>, seeds: &[LibrarySeed], dry_run: bool) -> Result<usize> {
+    let existing: Vec<TvSeed> = match tv_id {
+        Some(tv_id) => {
+            let wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), tv_id);
+            global::RB.fetch_list_by_wrapper(wrapper).await?
+        }
+        None => vec![],
+    };
+
+    let mut count = 0;
+    for seed in seeds {
+        let info_hash = link::info_hash(&seed.url);
+        let known = existing.iter().any(|old| {
+            old.url.as_deref() == Some(seed.url.as_str())
+                || (info_hash.is_some() && old.info_hash == info_hash)
+        });
+        if known {
+            continue;
+        }
+        count += 1;
+
+        if let (false, Some(tv_id)) = (dry_run, tv_id) {
+            let seed = TvSeed {
+                id: None,
+                tv_id: Some(tv_id),
+                ep: seed.ep,
+                url: Some(seed.url.clone()),
+                name: seed.name.clone(),
+                size: seed.size,
+                info_hash,
+                season: seed.season,
+                pinned: Some(seed.pinned).filter(|pinned| *pinned),
+                manual: Some(seed.manual).filter(|manual| *manual),
+            };
+            global::RB.save(&seed, &[]).await?;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use rbatis::{Page, PageRequest};
+
+    use super::*;
+    use crate::model::PageResponse;
+    use crate::tvs::tv_filter;
+
+    fn tv(id: i64, tvdbid: &str, name: &str) -> Tv {
+        Tv {
+            id: Some(id),
+            tvdbid: Some(tvdbid.to_string()),
+            tvname: Some(name.to_string()),
+            url: None,
+            name: Some(name.to_string()),
+            aliases: None,
+            updated_at: None,
+            sonarr_id: None,
+            removed: None,
+        }
+    }
+
+    fn item(tvdbid: &str, name: &str) -> LibraryTv {
+        LibraryTv {
+            tvdbid: tvdbid.to_string(),
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let library = Library {
+            version: VERSION,
+            tvs: vec![
+                LibraryTv {
+                    aliases: Some("Three-Body, 3体".to_string()),
+                    ..item("421026", "三体")
+                },
+                item("81189", "绝命毒师"),
+            ],
+        };
+
+        for format in [Format::Json, Format::Yaml, Format::Csv] {
+            let content = render(&library, format).unwrap();
+            assert_eq!(parse(&content, format).unwrap(), library, "{:?}", format);
+        }
+
+        let mut nested = library;
+        nested.tvs[0].overrides.push(LibraryOverride {
+            info_hash: "abc".to_string(),
+            blocked: true,
+            season: None,
+            ep: None,
+            pinned: None,
+        });
+        assert!(render(&nested, Format::Csv).is_err());
+        assert_eq!(
+            parse(&render(&nested, Format::Yaml).unwrap(), Format::Yaml).unwrap(),
+            nested
+        );
+    }
+
+    #[test]
+    fn test_plan() {
+        let existing = vec![tv(1, "421026", "三体"), tv(2, "81189", "绝命毒师")];
+        let incoming = vec![
+            item("421026", "三体"),
+            LibraryTv {
+                aliases: Some("Breaking Bad".to_string()),
+                ..item("81189", "")
+            },
+            item("305288", "怪奇物语"),
+            item("tt0903747", "错误"),
+            item("305288", "重复"),
+        ];
+
+        let actions: Vec<(Action, Vec<String>)> = plan(&existing, &incoming)
+            .into_iter()
+            .map(|change| (change.action, change.fields))
+            .collect();
+
+        assert_eq!(actions[0], (Action::Unchanged, vec![]));
+        assert_eq!(actions[1], (Action::Update, vec!["aliases".to_string()]));
+        assert_eq!(actions[2], (Action::Create, vec![]));
+        assert_eq!(to_tv(&incoming[2]).tvname.as_deref(), Some("怪奇物语"));
+        assert_eq!(actions[3].0, Action::Invalid);
+        assert_eq!(
+            actions[4].1,
+            vec!["tvdbid: 305288 appears more than once".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_merge_tvname() {
+        let saved = Tv {
+            tvname: None,
+            ..tv(1, "421026", "三体")
+        };
+        let (merged, fields) = merge(&saved, &item("421026", "三体"));
+        assert_eq!(merged.tvname.as_deref(), Some("三体"));
+        assert_eq!(fields, vec!["tvname".to_string()]);
+    }
+
+    #[async_std::test]
+    async fn test_import_without_tvname() {
+        global::connect_test().await;
+        let library = Library {
+            version: VERSION,
+            tvs: vec![item("90044", "无英文名")],
+        };
+        let report = import(&library, false).await.unwrap();
+        assert_eq!(report.created, 1);
+
+        let wrapper = tv_filter(Some("90044".to_string()));
+        let page: Page<Tv> = global::RB
+            .fetch_page_by_wrapper(wrapper, &PageRequest::new(1, 10))
+            .await
+            .unwrap();
+        let listed = PageResponse::from(page);
+        assert_eq!(listed.data.items[0].tvname, "无英文名");
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("tvs.yml")), Some(Format::Yaml));
+        assert_eq!(Format::from_path(Path::new("tvs.CSV")), Some(Format::Csv));
+        assert_eq!(Format::from_path(Path::new("tvs")), None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::task;
+
+use crate::define::ResolverDefine;
+
+/// Parallel requests per define when the define doesn't configure a limit.
+const DEFAULT_CONCURRENCY: usize = 1;
+
+lazy_static! {
+    pub static ref LIMITER: DomainLimiter = DomainLimiter::new();
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct LimitConfig {
+    concurrency: usize,
+    delay: Duration,
+}
+
+impl LimitConfig {
+    fn of(define: &ResolverDefine) -> Self {
+        let limit = define.limit.as_ref();
+        LimitConfig {
+            concurrency: limit
+                .and_then(|l| l.concurrency)
+                .unwrap_or(DEFAULT_CONCURRENCY)
+                .max(1),
+            delay: Duration::from_millis(limit.and_then(|l| l.delay).unwrap_or(0)),
+        }
+    }
+}
+
+struct Slot {
+    config: LimitConfig,
+    permits: (Sender<()>, Receiver<()>),
+    last_start: async_std::sync::Mutex<Option<Instant>>,
+}
+
+impl Slot {
+    fn new(config: LimitConfig) -> Self {
+        let permits = bounded(config.concurrency);
+        for _ in 0..config.concurrency {
+            permits.0.try_send(()).unwrap();
+        }
+        Slot {
+            config,
+            permits,
+            last_start: async_std::sync::Mutex::new(None),
+        }
+    }
+}
+
+/// Limits parallel requests and the delay between requests for each site define.
+pub struct DomainLimiter {
+    slots: Mutex<HashMap<String, Arc<Slot>>>,
+}
+
+/// Held while a request runs, frees the slot when dropped.
+pub struct Permit {
+    slot: Arc<Slot>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let _ = self.slot.permits.0.try_send(());
+    }
+}
+
+impl DomainLimiter {
+    fn new() -> Self {
+        DomainLimiter {
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn slot(&self, define: &ResolverDefine) -> Arc<Slot> {
+        let config = LimitConfig::of(define);
+        let mut slots = self.slots.lock().unwrap();
+        match slots.get(&define.id) {
+            Some(slot) if slot.config == config => slot.clone(),
+            _ => {
+                let slot = Arc::new(Slot::new(config));
+                slots.insert(define.id.to_string(), slot.clone());
+                slot
+            }
+        }
+    }
+
+    /// Waits for a free slot of the define, then for the configured delay since the last request.
+    pub async fn acquire(&self, define: &ResolverDefine) -> Permit {
+        let slot = self.slot(define);
+        slot.permits.1.recv().await.unwrap();
+        let permit = Permit { slot: slot.clone() };
+
+        let mut last_start = slot.last_start.lock().await;
+        if let Some(last) = *last_start {
+            let elapsed = last.elapsed();
+            if elapsed < slot.config.delay {
+                task::sleep(slot.config.delay - elapsed).await;
+            }
+        }
+        *last_start = Some(Instant::now());
+
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::define;
+
+    fn limited(concurrency: usize, delay: u64) -> ResolverDefine {
+        let mut define = define::all().into_iter().next().unwrap();
+        define.id = format!("limited-{}-{}", concurrency, delay);
+        define.limit = Some(define::ResolverLimitDefine {
+            concurrency: Some(concurrency),
+            delay: Some(delay),
+        });
+        define
+    }
+
+    #[async_std::test]
+    async fn test_concurrency() {
+        let limiter = DomainLimiter::new();
+        let define = limited(2, 0);
+
+        let first = limiter.acquire(&define).await;
+        let _second = limiter.acquire(&define).await;
+        let third =
+            async_std::future::timeout(Duration::from_millis(50), limiter.acquire(&define)).await;
+        assert!(third.is_err());
+
+        drop(first);
+        let third =
+            async_std::future::timeout(Duration::from_millis(50), limiter.acquire(&define)).await;
+        assert!(third.is_ok());
+    }
+
+    #[async_std::test]
+    async fn test_delay() {
+        let limiter = DomainLimiter::new();
+        let define = limited(2, 100);
+
+        let start = Instant::now();
+        let _first = limiter.acquire(&define).await;
+        let _second = limiter.acquire(&define).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use magnet_url::Magnet;
+use thiserror::Error;
+
+use crate::cache;
+use crate::charset;
+use crate::define::ResolverDefine;
+use crate::provider;
+use crate::torrent;
+
+#[derive(Debug, PartialEq)]
+pub enum LinkKind {
+    Magnet,
+    /// base64 wrapped `thunder://` link
+    Thunder,
+    Ed2k,
+    Torrent,
+    Unknown,
+}
+
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("Unsupported link: {0}")]
+    Unsupported(String),
+}
+
+/// A row link converted to a magnet.
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    pub magnet: String,
+    pub size: Option<u64>,
+}
+
+pub fn detect(url: &str) -> LinkKind {
+    let lower = url.trim().to_lowercase();
+    if lower.starts_with("magnet:") {
+        LinkKind::Magnet
+    } else if lower.starts_with("thunder://") {
+        LinkKind::Thunder
+    } else if lower.starts_with("ed2k://") {
+        LinkKind::Ed2k
+    } else if lower
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .map_or(false, |path| path.ends_with(".torrent"))
+    {
+        LinkKind::Torrent
+    } else {
+        LinkKind::Unknown
+    }
+}
+
+/// Reads the lowercase `btih` hash of a magnet.
+pub fn info_hash(magnet: &str) -> Option<String> {
+    let start = magnet.find("xt=urn:btih:")? + "xt=urn:btih:".len();
+    let hash = magnet[start..].split('&').next()?;
+    if hash.is_empty() {
+        None
+    } else {
+        Some(hash.to_lowercase())
+    }
+}
+
+/// Decodes `thunder://base64("AA" + url + "ZZ")` to the wrapped url.
+pub fn unwrap_thunder(url: &str) -> Result<String> {
+    let encoded = url.trim()["thunder://".len()..].trim_end_matches('/');
+    let decoded = charset::decode_html(&base64::decode(encoded)?, None, None);
+    let inner = decoded
+        .strip_prefix("AA")
+        .and_then(|inner| inner.strip_suffix("ZZ"))
+        .ok_or_else(|| anyhow!("bad thunder link {}", url))?;
+    Ok(inner.to_string())
+}
+
+/// Converts a row link to a magnet, without trackers and name unless the define keeps them.
+/// Relative `.torrent` links are resolved against the page, then downloaded to read their
+/// info hash and size.
+pub async fn resolve(define: Option<&ResolverDefine>, page_url: &str, url: &str) -> Result<Link> {
+    let url = match detect(url) {
+        LinkKind::Thunder => unwrap_thunder(url)?,
+        _ => url.to_string(),
+    };
+
+    let options = define.and_then(|d| d.magnet.clone()).unwrap_or_default();
+
+    match detect(&url) {
+        LinkKind::Magnet => {
+            let mut magneturl = Magnet::new(&url).map_err(|e| anyhow!("{:?}", e))?;
+            if !options.keep_trackers {
+                magneturl.tr.clear();
+            }
+            if !options.keep_name {
+                magneturl.dn = None;
+            }
+            Ok(Link {
+                magnet: magneturl.to_string(),
+                size: magneturl.xl.map(|xl| xl as u64),
+            })
+        }
+        LinkKind::Torrent => {
+            let define = define.ok_or_else(|| LinkError::Unsupported(url.to_string()))?;
+            let torrent_url = reqwest::Url::parse(page_url)?.join(&url)?;
+            info!("download torrent {}", torrent_url);
+            let bytes = provider::download(define, torrent_url.as_str()).await?;
+            let info = torrent::parse(&bytes)?;
+            cache::save(&info.info_hash, &bytes);
+            let magnet = match info.name.as_ref().filter(|_| options.keep_name) {
+                Some(name) => format!("{}&dn={}", info.magnet(), urlencoding::encode(name)),
+                None => info.magnet(),
+            };
+            Ok(Link {
+                magnet,
+                size: Some(info.size),
+            })
+        }
+        _ => Err(LinkError::Unsupported(url).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("magnet:?xt=urn:btih:abc"), LinkKind::Magnet);
+        assert_eq!(detect("thunder://QUFaWg=="), LinkKind::Thunder);
+        assert_eq!(detect("ed2k://|file|a.mp4|1|ABC|/"), LinkKind::Ed2k);
+        assert_eq!(detect("/down/1.torrent?t=1"), LinkKind::Torrent);
+        assert_eq!(detect("https://example.com/1.html"), LinkKind::Unknown);
+    }
+
+    #[test]
+    fn test_info_hash() {
+        assert_eq!(
+            info_hash("magnet:?xt=urn:btih:ABCDEF&dn=a").as_deref(),
+            Some("abcdef")
+        );
+        assert_eq!(info_hash("magnet:?dn=a"), None);
+    }
+
+    #[test]
+    fn test_unwrap_thunder() {
+        let inner = "magnet:?xt=urn:btih:0123456789ABCDEF0123456789ABCDEF01234567";
+        let url = format!("thunder://{}", base64::encode(format!("AA{}ZZ", inner)));
+
+        assert_eq!(unwrap_thunder(&url).unwrap(), inner);
+        assert!(unwrap_thunder("thunder://bm90IHdyYXBwZWQ=").is_err());
+    }
+
+    #[async_std::test]
+    async fn test_resolve_thunder_magnet() {
+        let inner =
+            "magnet:?xt=urn:btih:0123456789ABCDEF0123456789ABCDEF01234567&dn=a&tr=udp%3A%2F%2Ft";
+        let url = format!("thunder://{}", base64::encode(format!("AA{}ZZ", inner)));
+
+        let link = resolve(None, "https://example.com/", &url).await.unwrap();
+
+        assert_eq!(
+            link.magnet,
+            "magnet:?xt=urn:btih:0123456789ABCDEF0123456789ABCDEF01234567"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_resolve_ed2k() {
+        let result = resolve(None, "https://example.com/", "ed2k://|file|a.mp4|1|ABC|/").await;
+        assert!(result.is_err());
+    }
+}
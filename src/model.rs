@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use rbatis::Page;
 
 #[crud_table]
@@ -8,6 +10,13 @@ pub struct Tv {
     pub tvname: Option<String>,
     pub url: Option<String>,
     pub name: Option<String>,
+    pub aliases: Option<String>,
+    /// RFC 3339 time of the last change or newly found seed
+    pub updated_at: Option<String>,
+    /// series id when the show is synced from Sonarr
+    pub sonarr_id: Option<i64>,
+    /// no longer monitored in Sonarr
+    pub removed: Option<bool>,
 }
 
 #[crud_table]
@@ -18,18 +27,74 @@ pub struct TvSeed {
     pub ep: Option<i64>,
     pub url: Option<String>,
     pub name: Option<String>,
+    pub size: Option<i64>,
+    pub info_hash: Option<String>,
+    pub season: Option<i64>,
+    pub pinned: Option<bool>,
+    /// added by hand, kept when the show is scraped again
+    pub manual: Option<bool>,
+}
+
+/// Changes to the seeds of an info hash, applied again on every scrape.
+#[crud_table]
+#[derive(Clone, Debug, schemars::JsonSchema)]
+pub struct TvSeedOverride {
+    pub id: Option<i64>,
+    pub tv_id: Option<i64>,
+    pub info_hash: Option<String>,
+    pub blocked: Option<bool>,
+    pub season: Option<i64>,
+    pub ep: Option<i64>,
+    pub pinned: Option<bool>,
 }
 
-impl_field_name_method!(TvSeed { id, tv_id, ep });
-impl_field_name_method!(Tv { id, tvdbid });
+impl_field_name_method!(TvSeed {
+    id,
+    tv_id,
+    ep,
+    season,
+    name,
+    size,
+    info_hash,
+    pinned,
+    manual
+});
+impl_field_name_method!(TvSeedOverride {
+    id,
+    tv_id,
+    info_hash
+});
+impl_field_name_method!(Tv {
+    id,
+    tvdbid,
+    name,
+    tvname,
+    aliases,
+    updated_at,
+    sonarr_id
+});
 
-#[derive(serde::Deserialize, serde::Serialize, Debug)]
+/// Current time in the format of `Tv::updated_at`, sortable as text.
+pub fn timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
 pub struct PageResponse<T> {
     pub status: u8,
     pub msg: String,
     pub data: PageDataResponse<T>,
 }
 
+impl<T> PageResponse<T> {
+    /// Reports the sort applied to the page.
+    pub fn sorted(mut self, order_by: &str, order_dir: &str) -> Self {
+        self.data.order_by = Some(order_by.to_string());
+        self.data.order_dir = Some(order_dir.to_string());
+        self
+    }
+}
+
 impl From<Page<Tv>> for PageResponse<TvResponse> {
     fn from(o: Page<Tv>) -> Self {
         let tvs: Vec<TvResponse> = o
@@ -43,6 +108,8 @@ impl From<Page<Tv>> for PageResponse<TvResponse> {
             data: PageDataResponse {
                 items: tvs,
                 total: o.total,
+                order_by: None,
+                order_dir: None,
             },
         };
         x
@@ -62,6 +129,8 @@ impl From<Page<TvSeed>> for PageResponse<TvSeedResponse> {
             data: PageDataResponse {
                 items: seeds,
                 total: o.total,
+                order_by: None,
+                order_dir: None,
             },
         }
     }
@@ -72,41 +141,61 @@ impl From<Tv> for TvResponse {
         TvResponse {
             id: o.id.unwrap(),
             tvdbid: o.tvdbid.unwrap(),
-            tvname: o.tvname.unwrap(),
-            url: o.url.unwrap(),
+            tvname: o.tvname.or_else(|| o.name.clone()).unwrap_or_default(),
+            url: o.url.unwrap_or_default(),
             name: o.name.unwrap(),
+            aliases: o.aliases.unwrap_or_default(),
+            updated_at: o.updated_at.unwrap_or_default(),
+            sonarr_id: o.sonarr_id,
+            removed: o.removed.unwrap_or(false),
         }
     }
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
 pub struct TvResponse {
     pub id: i64,
     pub tvdbid: String,
     pub tvname: String,
     pub url: String,
     pub name: String,
+    pub aliases: String,
+    pub updated_at: String,
+    pub sonarr_id: Option<i64>,
+    pub removed: bool,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
 pub struct TvSeedResponse {
     pub id: i64,
     pub tv_id: i64,
     pub ep: i64,
     pub url: String,
     pub name: String,
+    pub size: i64,
+    pub info_hash: String,
+    pub season: Option<i64>,
+    pub pinned: bool,
+    pub manual: bool,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
 pub struct PageDataResponse<T> {
     pub items: Vec<T>,
     pub total: u64,
+    #[serde(rename = "orderBy", skip_serializing_if = "Option::is_none")]
+    pub order_by: Option<String>,
+    #[serde(rename = "orderDir", skip_serializing_if = "Option::is_none")]
+    pub order_dir: Option<String>,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Debug, Default)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Default, schemars::JsonSchema)]
 pub struct OperationResponse {
     pub status: u8,
     pub msg: String,
+    /// messages by field name when a request is invalid
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub errors: HashMap<String, String>,
 }
 
 impl OperationResponse {
@@ -114,6 +203,39 @@ impl OperationResponse {
         let r: OperationResponse = Default::default();
         r
     }
+
+    pub fn failure(msg: &str) -> Self {
+        OperationResponse {
+            status: 1,
+            msg: msg.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn invalid(errors: HashMap<String, String>) -> Self {
+        OperationResponse {
+            status: 1,
+            msg: "invalid request".to_string(),
+            errors,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, schemars::JsonSchema)]
+pub struct DataResponse<T> {
+    pub status: u8,
+    pub msg: String,
+    pub data: T,
+}
+
+impl<T> DataResponse<T> {
+    pub fn new(data: T) -> Self {
+        DataResponse {
+            status: 0,
+            msg: "".to_string(),
+            data,
+        }
+    }
 }
 
 impl From<TvSeed> for TvSeedResponse {
@@ -124,6 +246,11 @@ impl From<TvSeed> for TvSeedResponse {
             ep: s.ep.unwrap(),
             url: s.url.unwrap(),
             name: s.name.unwrap(),
+            size: s.size.unwrap_or(0),
+            info_hash: s.info_hash.unwrap_or_default(),
+            season: s.season,
+            pinned: s.pinned.unwrap_or(false),
+            manual: s.manual.unwrap_or(false),
         }
     }
 }
@@ -0,0 +1,511 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+
+use crate::downloader::PushOptions;
+use crate::http::{
+    ApiRequest, DownloadPathRequest, ExportRequest, ImportRequest, OverridePathRequest,
+    SeedAddRequest, SeedPatchRequest, SeedPathRequest, SeedsPathRequest, SeedsRequest,
+    SonarrSyncRequest, TvAddRequest, TvDeleteRequest, TvPatchRequest, TvPathRequest, TvsRequest,
+};
+use crate::library::ImportReport;
+use crate::model::{
+    DataResponse, OperationResponse, PageResponse, TvResponse, TvSeedOverride, TvSeedResponse,
+};
+use crate::server;
+use crate::sonarr::SyncReport;
+
+lazy_static! {
+    static ref DOCUMENT: Value = document();
+}
+
+/// The OpenAPI 3.1 description of the http api, built once.
+pub fn get() -> &'static Value {
+    &DOCUMENT
+}
+
+struct Builder {
+    gen: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        // OpenAPI 3.1 schemas are JSON Schema 2019-09 and later
+        let settings = SchemaSettings::draft2019_09().with(|s| {
+            s.definitions_path = "#/components/schemas/".to_string();
+        });
+        Builder {
+            gen: settings.into_generator(),
+            paths: Map::new(),
+        }
+    }
+
+    fn schema<T: JsonSchema>(&mut self) -> Value {
+        serde_json::to_value(self.gen.subschema_for::<T>()).unwrap()
+    }
+
+    /// Parameters from the fields of a query or path extractor.
+    fn params<T: JsonSchema>(&mut self, location: &str) -> Vec<Value> {
+        let root = self.gen.root_schema_for::<T>();
+        let object = match root.schema.object {
+            Some(object) => object,
+            None => return vec![],
+        };
+        object
+            .properties
+            .iter()
+            .map(|(name, schema)| {
+                let description = match schema {
+                    Schema::Object(o) => o.metadata.as_ref().and_then(|m| m.description.clone()),
+                    _ => None,
+                };
+                let mut param = json!({
+                    "name": name,
+                    "in": location,
+                    "required": location == "path" || object.required.contains(name),
+                    "schema": schema,
+                });
+                if let Some(description) = description {
+                    param["description"] = json!(description);
+                }
+                param
+            })
+            .collect()
+    }
+
+    fn json_body<T: JsonSchema>(&mut self) -> Value {
+        json!({
+            "required": true,
+            "content": { "application/json": { "schema": self.schema::<T>() } }
+        })
+    }
+
+    fn json_ok<T: JsonSchema>(&mut self) -> Value {
+        json!({
+            "200": {
+                "description": "OK",
+                "content": { "application/json": { "schema": self.schema::<T>() } }
+            }
+        })
+    }
+
+    /// Adds an error response with an `OperationResponse` body.
+    fn failure(&mut self, mut responses: Value, status: &str, description: &str) -> Value {
+        responses[status] = json!({
+            "description": description,
+            "content": {
+                "application/json": { "schema": self.schema::<OperationResponse>() }
+            }
+        });
+        responses
+    }
+
+    fn failures(&mut self, mut responses: Value, failures: &[(&str, &str)]) -> Value {
+        for (status, description) in failures {
+            responses = self.failure(responses, status, description);
+        }
+        responses
+    }
+
+    fn add(&mut self, method: &str, path: &str, summary: &str, mut operation: Value) {
+        operation["summary"] = json!(summary);
+        if path != "/health" && path != "/api" {
+            operation["responses"] =
+                self.failure(operation["responses"].take(), "default", "Failure");
+        }
+        let item = self
+            .paths
+            .entry(path.to_string())
+            .or_insert_with(|| json!({}));
+        item[method] = operation;
+    }
+
+    fn build(mut self) -> Value {
+        let schemas = self.gen.take_definitions();
+        let server = match server::base_path() {
+            base_path if base_path.is_empty() => "/".to_string(),
+            base_path => base_path,
+        };
+        json!({
+            "openapi": "3.1.0",
+            "info": {
+                "title": "wkavu",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "servers": [{ "url": server }],
+            "paths": self.paths,
+            "components": { "schemas": schemas },
+        })
+    }
+}
+
+fn document() -> Value {
+    let mut b = Builder::new();
+
+    let op = json!({ "responses": { "200": { "description": "server is up!" } } });
+    b.add("get", "/health", "Health check", op);
+
+    let op = json!({
+        "parameters": b.params::<ApiRequest>("query"),
+        "responses": {
+            "200": {
+                "description": "Torznab caps or search results",
+                "content": { "application/xml": { "schema": { "type": "string" } } }
+            }
+        }
+    });
+    b.add("get", "/api", "Torznab api", op);
+
+    let responses = json!({
+        "200": {
+            "description": "Cached torrent file",
+            "content": { "application/x-bittorrent": {} }
+        },
+        "302": { "description": "Redirect to the magnet" }
+    });
+    let op = json!({
+        "parameters": b.params::<DownloadPathRequest>("path"),
+        "responses": b.failure(responses, "404", "Unknown seed")
+    });
+    b.add("get", "/download/{seed_id}", "Download a seed", op);
+
+    let op = json!({ "responses": b.json_ok::<OperationResponse>() });
+    b.add("get", "/admin/fetch", "Fetch every show", op);
+
+    let op = json!({ "responses": b.json_ok::<OperationResponse>() });
+    b.add("get", "/admin/defines/reload", "Reload site defines", op);
+
+    let mut op = json!({
+        "parameters": b.params::<SonarrSyncRequest>("query"),
+        "responses": b.json_ok::<DataResponse<SyncReport>>()
+    });
+    let responses = b.failure(op["responses"].take(), "502", "Sonarr failed");
+    op["responses"] = b.failure(responses, "503", "Sonarr is not configured");
+    b.add("post", "/admin/sonarr/sync", "Sync shows with Sonarr", op);
+
+    let op = json!({
+        "parameters": b.params::<TvsRequest>("query"),
+        "responses": b.json_ok::<PageResponse<TvResponse>>()
+    });
+    b.add("get", "/admin/tvs", "List shows", op);
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "requestBody": b.json_body::<TvAddRequest>(),
+        "responses": b.failure(responses, "422", "Invalid show, with errors by field")
+    });
+    b.add("post", "/admin/tvs", "Add a show", op);
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "requestBody": b.json_body::<TvDeleteRequest>(),
+        "responses": b.failure(responses, "404", "Unknown show")
+    });
+    b.add("post", "/admin/tvs/delete", "Delete a show", op);
+
+    let responses = json!({
+        "200": {
+            "description": "Show library",
+            "content": {
+                "application/json": {},
+                "application/x-yaml": {},
+                "text/csv": {}
+            }
+        }
+    });
+    let op = json!({
+        "parameters": b.params::<ExportRequest>("query"),
+        "responses": b.failure(responses, "400", "Unknown format")
+    });
+    b.add("get", "/admin/tvs/export", "Export the show library", op);
+
+    let responses = b.json_ok::<DataResponse<ImportReport>>();
+    let op = json!({
+        "parameters": b.params::<ImportRequest>("query"),
+        "requestBody": {
+            "required": true,
+            "content": {
+                "application/json": {},
+                "application/x-yaml": {},
+                "text/csv": {}
+            }
+        },
+        "responses": b.failure(responses, "400", "Unknown format or unreadable library")
+    });
+    b.add("post", "/admin/tvs/import", "Import a show library", op);
+
+    let responses = b.json_ok::<DataResponse<TvResponse>>();
+    let op = json!({
+        "parameters": b.params::<TvPathRequest>("path"),
+        "responses": b.failure(responses, "404", "Unknown show")
+    });
+    b.add("get", "/admin/tvs/{id}", "Get a show", op);
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<TvPathRequest>("path"),
+        "requestBody": b.json_body::<TvAddRequest>(),
+        "responses": b.failures(
+            responses,
+            &[("404", "Unknown show"), ("422", "Invalid show, with errors by field")]
+        )
+    });
+    b.add("put", "/admin/tvs/{id}", "Replace a show", op);
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<TvPathRequest>("path"),
+        "requestBody": b.json_body::<TvPatchRequest>(),
+        "responses": b.failures(
+            responses,
+            &[("404", "Unknown show"), ("422", "Invalid show, with errors by field")]
+        )
+    });
+    b.add("patch", "/admin/tvs/{id}", "Change fields of a show", op);
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<TvPathRequest>("path"),
+        "responses": b.failure(responses, "404", "Unknown show")
+    });
+    b.add(
+        "delete",
+        "/admin/tvs/{id}",
+        "Delete a show and its seeds",
+        op,
+    );
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<TvPathRequest>("path"),
+        "requestBody": b.json_body::<SeedAddRequest>(),
+        "responses": b.failures(
+            responses,
+            &[("404", "Unknown show"), ("422", "Not a magnet link")]
+        )
+    });
+    b.add("post", "/admin/tvs/{id}/seeds", "Add a magnet by hand", op);
+
+    let op = json!({
+        "parameters": b.params::<TvPathRequest>("path"),
+        "responses": b.json_ok::<DataResponse<Vec<TvSeedOverride>>>()
+    });
+    b.add(
+        "get",
+        "/admin/tvs/{id}/overrides",
+        "List seed overrides",
+        op,
+    );
+
+    let mut params = b.params::<SeedsPathRequest>("path");
+    params.extend(b.params::<SeedsRequest>("query"));
+    let op = json!({
+        "parameters": params,
+        "responses": b.json_ok::<PageResponse<TvSeedResponse>>()
+    });
+    b.add(
+        "get",
+        "/admin/seeds/tvid/{tvid}",
+        "List seeds of a show",
+        op,
+    );
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<SeedPathRequest>("path"),
+        "requestBody": b.json_body::<PushOptions>(),
+        "responses": b.failures(
+            responses,
+            &[
+                ("404", "Unknown seed"),
+                ("502", "The download client failed"),
+                ("503", "No download client is configured")
+            ]
+        )
+    });
+    b.add(
+        "post",
+        "/admin/seeds/{seed_id}/push",
+        "Push a seed to the download client",
+        op,
+    );
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<SeedPathRequest>("path"),
+        "responses": b.failures(
+            responses,
+            &[("400", "The seed has no info hash"), ("404", "Unknown seed")]
+        )
+    });
+    b.add(
+        "post",
+        "/admin/seeds/{seed_id}/block",
+        "Block a seed by info hash",
+        op,
+    );
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<SeedPathRequest>("path"),
+        "requestBody": b.json_body::<SeedPatchRequest>(),
+        "responses": b.failures(
+            responses,
+            &[("400", "The seed has no info hash"), ("404", "Unknown seed")]
+        )
+    });
+    b.add("patch", "/admin/seeds/{seed_id}", "Remap or pin a seed", op);
+
+    let responses = b.json_ok::<OperationResponse>();
+    let op = json!({
+        "parameters": b.params::<SeedPathRequest>("path"),
+        "responses": b.failure(responses, "404", "Unknown seed")
+    });
+    b.add("delete", "/admin/seeds/{seed_id}", "Delete a seed", op);
+
+    let op = json!({
+        "parameters": b.params::<OverridePathRequest>("path"),
+        "responses": b.json_ok::<OperationResponse>()
+    });
+    b.add(
+        "delete",
+        "/admin/overrides/{id}",
+        "Delete a seed override",
+        op,
+    );
+
+    b.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App};
+    use jsonschema::JSONSchema;
+    use rbatis::Page;
+
+    use super::*;
+    use crate::http;
+    use crate::model::Tv;
+
+    /// Checks a response body against the documented response of an operation.
+    fn assert_contract(method: &str, path: &str, status: u16, body: &Value) {
+        let document = get();
+        let responses = &document["paths"][path][method]["responses"];
+        let response = match &responses[status.to_string()] {
+            Value::Null => &responses["default"],
+            response => response,
+        };
+        let mut schema = response["content"]["application/json"]["schema"].clone();
+        assert!(
+            !schema.is_null(),
+            "{} {} {} is not documented",
+            method,
+            path,
+            status
+        );
+        schema["components"] = document["components"].clone();
+
+        let compiled = JSONSchema::compile(&schema).unwrap();
+        if let Err(errors) = compiled.validate(body) {
+            let errors: Vec<String> = errors.map(|e| e.to_string()).collect();
+            panic!(
+                "{} {} response {} breaks the contract: {:?}",
+                method, path, body, errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_document() {
+        let document = get();
+        assert_eq!(document["openapi"], "3.1.0");
+        assert!(document["components"]["schemas"]["OperationResponse"].is_object());
+
+        let params = document["paths"]["/admin/tvs"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert!(params
+            .iter()
+            .any(|p| p["name"] == "keyword" && p["in"] == "query" && p["description"].is_string()));
+        assert_eq!(
+            document["paths"]["/admin/tvs/{id}"]["get"]["parameters"][0]["required"],
+            true
+        );
+    }
+
+    #[test]
+    fn test_page_contracts() {
+        let tv = Tv {
+            id: Some(1),
+            tvdbid: Some("421026".to_string()),
+            tvname: Some("Three-Body".to_string()),
+            url: None,
+            name: Some("三体".to_string()),
+            aliases: None,
+            updated_at: None,
+            sonarr_id: None,
+            removed: None,
+        };
+        let mut page: Page<Tv> = Page::new(1, 10);
+        page.records = vec![tv.clone()];
+        page.total = 1;
+        let response = PageResponse::from(page).sorted("id", "asc");
+        assert_contract(
+            "get",
+            "/admin/tvs",
+            200,
+            &serde_json::to_value(response).unwrap(),
+        );
+
+        let response = DataResponse::new(TvResponse::from(tv));
+        assert_contract(
+            "get",
+            "/admin/tvs/{id}",
+            200,
+            &serde_json::to_value(response).unwrap(),
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_handler_contracts() {
+        let mut app = test::init_service(
+            App::new()
+                .route("/admin/tvs", web::post().to(http::tv_add))
+                .route("/admin/defines/reload", web::get().to(http::define_reload))
+                .route("/admin/sonarr/sync", web::post().to(http::sonarr_sync)),
+        )
+        .await;
+
+        // invalid shows are rejected before the database is used
+        let req = test::TestRequest::post()
+            .uri("/admin/tvs")
+            .set_json(&json!({ "name": "", "tvdbid": "tt0903747", "tvname": "" }))
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let status = resp.status().as_u16();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, 422);
+        assert_eq!(body["status"], 1);
+        assert_eq!(body["errors"]["tvdbid"], "tvdbid must be a number");
+        assert_contract("post", "/admin/tvs", status, &body);
+
+        let req = test::TestRequest::get()
+            .uri("/admin/defines/reload")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let status = resp.status().as_u16();
+        let body: Value = test::read_body_json(resp).await;
+        assert_contract("get", "/admin/defines/reload", status, &body);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/sonarr/sync")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        let status = resp.status().as_u16();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, 503);
+        assert_contract("post", "/admin/sonarr/sync", status, &body);
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use rbatis::crud::CRUD;
+
+use crate::global;
+use crate::model::{TvSeed, TvSeedOverride};
+use crate::resolver;
+
+pub async fn load(tv_id: i64) -> Result<Vec<TvSeedOverride>> {
+    let wrapper = global::RB.new_wrapper().eq(TvSeedOverride::tv_id(), tv_id);
+    Ok(global::RB.fetch_list_by_wrapper(wrapper).await?)
+}
+
+/// Creates or changes the override of an info hash.
+pub async fn save<F>(tv_id: i64, info_hash: &str, change: F) -> Result<TvSeedOverride>
+where
+    F: FnOnce(&mut TvSeedOverride),
+{
+    let wrapper = global::RB
+        .new_wrapper()
+        .eq(TvSeedOverride::tv_id(), tv_id)
+        .and()
+        .eq(TvSeedOverride::info_hash(), info_hash);
+    let existing: Option<TvSeedOverride> = global::RB.fetch_by_wrapper(wrapper).await?;
+
+    let mut seed_override = existing.unwrap_or(TvSeedOverride {
+        id: None,
+        tv_id: Some(tv_id),
+        info_hash: Some(info_hash.to_string()),
+        blocked: None,
+        season: None,
+        ep: None,
+        pinned: None,
+    });
+    change(&mut seed_override);
+
+    if seed_override.id.is_some() {
+        global::RB
+            .update_by_column(TvSeedOverride::id(), &seed_override)
+            .await?;
+    } else {
+        global::RB.save(&seed_override, &[]).await?;
+    }
+    Ok(seed_override)
+}
+
+/// Applies the override of a seed's info hash, `None` when the seed is blocked.
+pub fn apply(tvname: &str, overrides: &[TvSeedOverride], mut seed: TvSeed) -> Option<TvSeed> {
+    let seed_override = match &seed.info_hash {
+        Some(info_hash) => overrides
+            .iter()
+            .find(|o| o.info_hash.as_ref() == Some(info_hash)),
+        None => None,
+    };
+
+    if let Some(seed_override) = seed_override {
+        if seed_override.blocked.unwrap_or(false) {
+            return None;
+        }
+        if seed_override.season.is_some() {
+            seed.season = seed_override.season;
+        }
+        if seed_override.ep.is_some() {
+            seed.ep = seed_override.ep;
+        }
+        if seed_override.pinned.is_some() {
+            seed.pinned = seed_override.pinned;
+        }
+        if seed_override.season.is_some() || seed_override.ep.is_some() {
+            resolver::rename(tvname, &mut seed);
+        }
+    }
+    Some(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed(info_hash: &str) -> TvSeed {
+        TvSeed {
+            id: None,
+            tv_id: Some(1),
+            ep: Some(3),
+            url: Some(format!("magnet:?xt=urn:btih:{}", info_hash)),
+            name: Some("Three-Body S01E3 - 3 - [chinese] - 三体 第03集 - Wkavu".to_string()),
+            size: None,
+            info_hash: Some(info_hash.to_string()),
+            season: None,
+            pinned: None,
+            manual: None,
+        }
+    }
+
+    fn seed_override(info_hash: &str) -> TvSeedOverride {
+        TvSeedOverride {
+            id: Some(1),
+            tv_id: Some(1),
+            info_hash: Some(info_hash.to_string()),
+            blocked: None,
+            season: None,
+            ep: None,
+            pinned: None,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let overrides = vec![
+            TvSeedOverride {
+                blocked: Some(true),
+                ..seed_override("aaa")
+            },
+            TvSeedOverride {
+                season: Some(2),
+                ep: Some(4),
+                pinned: Some(true),
+                ..seed_override("bbb")
+            },
+        ];
+
+        assert!(apply("Three-Body", &overrides, seed("aaa")).is_none());
+
+        let changed = apply("Three-Body", &overrides, seed("bbb")).unwrap();
+        assert_eq!(changed.season, Some(2));
+        assert_eq!(changed.ep, Some(4));
+        assert_eq!(changed.pinned, Some(true));
+        assert_eq!(
+            changed.name.as_deref(),
+            Some("Three-Body S02E4 - 4 - [chinese] - 三体 第03集 - Wkavu")
+        );
+
+        let unchanged = apply("Three-Body", &overrides, seed("ccc")).unwrap();
+        assert_eq!(unchanged.ep, Some(3));
+        assert_eq!(unchanged.name, seed("ccc").name);
+        assert_eq!(unchanged.pinned, None);
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_std::task;
+use async_trait::async_trait;
+use headless_chrome::{Element, Tab};
+use log::{info, warn};
+use reqwest::header;
+
+use crate::browser;
+use crate::charset;
+use crate::define::{BrowserAction, ContentProviderType, ResolverDefine, ResolverHttpDefine};
+use crate::limiter::LIMITER;
+
+/// Returns the html of a page, so parsing doesn't depend on how the page is fetched.
+#[async_trait]
+pub trait ContentProvider: Send + Sync {
+    async fn get(&self, define: &ResolverDefine, url: &str) -> Result<String>;
+}
+
+/// Picks the provider configured by a define, `Chrome` when nothing is configured.
+pub fn for_define(define: &ResolverDefine) -> &'static dyn ContentProvider {
+    match define
+        .provider
+        .as_ref()
+        .unwrap_or(&ContentProviderType::Chrome)
+    {
+        ContentProviderType::Reqwest => &ReqwestProvider,
+        ContentProviderType::Chrome => &ChromeProvider,
+    }
+}
+
+pub const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36";
+
+lazy_static! {
+    static ref AGENT: RwLock<String> = RwLock::new(USER_AGENT.to_string());
+}
+
+/// Changes the user agent of http fetches, for clients built afterwards.
+pub fn set_user_agent(user_agent: &str) {
+    *AGENT.write().unwrap() = user_agent.to_string();
+}
+
+lazy_static! {
+    /// One client per define, rebuilt when the define's http options change.
+    static ref CLIENTS: Mutex<HashMap<String, (Option<ResolverHttpDefine>, reqwest::Client)>> =
+        Mutex::new(HashMap::new());
+}
+
+pub struct ReqwestProvider;
+
+impl ReqwestProvider {
+    fn client(define: &ResolverDefine) -> Result<reqwest::Client> {
+        let mut clients = CLIENTS.lock().unwrap();
+        if let Some((http, client)) = clients.get(&define.id) {
+            if *http == define.http {
+                return Ok(client.clone());
+            }
+        }
+
+        let client = build_client(define)?;
+        clients.insert(define.id.to_string(), (define.http.clone(), client.clone()));
+        Ok(client)
+    }
+}
+
+fn build_client(define: &ResolverDefine) -> Result<reqwest::Client> {
+    let http = define.http.clone().unwrap_or_default();
+
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::USER_AGENT,
+        header::HeaderValue::from_str(&AGENT.read().unwrap())?,
+    );
+    for (name, value) in &http.headers {
+        headers.insert(
+            header::HeaderName::from_bytes(name.as_bytes())?,
+            header::HeaderValue::from_str(value)?,
+        );
+    }
+    if let Some(cookies) = &http.cookies {
+        headers.insert(header::COOKIE, header::HeaderValue::from_str(cookies)?);
+    }
+
+    // get a client builder
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .timeout(Duration::from_secs(http.timeout.unwrap_or(define.timeout)));
+    if let Some(proxy) = &http.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[async_trait]
+impl ContentProvider for ReqwestProvider {
+    async fn get(&self, define: &ResolverDefine, url: &str) -> Result<String> {
+        let client = Self::client(define)?;
+        let http = define.http.clone().unwrap_or_default();
+        let retries = http.retries.unwrap_or(0);
+        let mut backoff = Duration::from_millis(http.backoff.unwrap_or(1000));
+
+        let mut attempt = 0;
+        let res = loop {
+            let res = client
+                .get(url)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status());
+            match res {
+                Ok(res) => break res,
+                Err(e) if attempt < retries => {
+                    warn!("fetch {} failed, retry in {:?}: {}", url, backoff, e);
+                    task::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        let content_type = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let bytes = res.bytes().await?;
+        Ok(charset::decode_html(
+            &bytes,
+            content_type.as_deref(),
+            http.charset.as_deref(),
+        ))
+    }
+}
+
+/// Downloads a file with the define's http client, e.g. a `.torrent` linked from a page.
+pub async fn download(define: &ResolverDefine, url: &str) -> Result<Vec<u8>> {
+    let client = ReqwestProvider::client(define)?;
+    let _permit = LIMITER.acquire(define).await;
+    let res = client.get(url).send().await?.error_for_status()?;
+    Ok(res.bytes().await?.to_vec())
+}
+
+pub struct ChromeProvider;
+
+#[async_trait]
+impl ContentProvider for ChromeProvider {
+    async fn get(&self, define: &ResolverDefine, url: &str) -> Result<String> {
+        let define = define.clone();
+        let url = url.to_string();
+
+        task::spawn_blocking(move || {
+            let mut tab = browser::POOL.acquire()?;
+            info!("browser tab is ready");
+
+            let html = render(&tab, &define, &url);
+            if html.is_err() {
+                tab.mark_broken();
+            }
+            html
+        })
+        .await
+    }
+}
+
+fn render(tab: &Tab, define: &ResolverDefine, url: &str) -> Result<String> {
+    tab.navigate_to(url).map_err(|e| anyhow!("{}", e))?;
+
+    if let Some(wait) = define.search.wait.as_ref() {
+        info!("waiting for special button");
+        tab.wait_for_element_with_custom_timeout(wait, Duration::from_secs(define.timeout))
+            .map_err(|e| anyhow!("{}", e))?;
+    }
+
+    for action in define.search.actions.iter().flatten() {
+        info!("run browser action {:?}", action);
+        run_action(tab, define, action)?;
+    }
+
+    let root_div: Element = tab.wait_for_element("body").map_err(|e| anyhow!("{}", e))?;
+    let html = root_div
+        .call_js_fn("function() { return this.innerHTML;}", true)
+        .map_err(|e| anyhow!("{}", e))?
+        .value
+        .ok_or_else(|| anyhow!("can't read body of {}", url))?;
+
+    Ok(html.as_str().unwrap_or_default().to_string())
+}
+
+fn run_action(tab: &Tab, define: &ResolverDefine, action: &BrowserAction) -> Result<()> {
+    let timeout = Duration::from_secs(define.timeout);
+    let find = |selector: &str| {
+        tab.wait_for_element_with_custom_timeout(selector, timeout)
+            .map_err(|e| anyhow!("{}", e))
+    };
+
+    match action {
+        BrowserAction::Wait(selector) => {
+            find(selector)?;
+        }
+        BrowserAction::Click(selector) => {
+            find(selector)?.click().map_err(|e| anyhow!("{}", e))?;
+        }
+        BrowserAction::Scroll(selector) => {
+            find(selector)?
+                .scroll_into_view()
+                .map_err(|e| anyhow!("{}", e))?;
+        }
+        BrowserAction::ScrollBottom => {
+            tab.evaluate("window.scrollTo(0, document.body.scrollHeight)", false)
+                .map_err(|e| anyhow!("{}", e))?;
+        }
+        BrowserAction::Eval(script) => {
+            tab.evaluate(script, true).map_err(|e| anyhow!("{}", e))?;
+        }
+        BrowserAction::Sleep(ms) => std::thread::sleep(Duration::from_millis(*ms)),
+        BrowserAction::Frame(selector) => {
+            let src = find(selector)?
+                .call_js_fn("function() { return this.src;}", false)
+                .map_err(|e| anyhow!("{}", e))?
+                .value
+                .and_then(|value| value.as_str().map(|src| src.to_string()))
+                .ok_or_else(|| anyhow!("frame {} has no src", selector))?;
+            let src = reqwest::Url::parse(&tab.get_url())?.join(&src)?;
+            tab.navigate_to(src.as_str())
+                .and_then(|tab| tab.wait_until_navigated())
+                .map_err(|e| anyhow!("{}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Serves saved pages from disk, used by fixtures and offline runs.
+#[derive(Default)]
+pub struct FileProvider {
+    pages: HashMap<String, PathBuf>,
+}
+
+impl FileProvider {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, url: &str, path: &Path) {
+        self.pages.insert(url.to_string(), path.to_path_buf());
+    }
+}
+
+#[async_trait]
+impl ContentProvider for FileProvider {
+    async fn get(&self, _define: &ResolverDefine, url: &str) -> Result<String> {
+        let path = self
+            .pages
+            .get(url)
+            .ok_or_else(|| anyhow!("no saved page for {}", url))?;
+        Ok(charset::decode_html(&fs::read(path)?, None, None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::define;
+    use crate::mock::{MockResponse, MockServer};
+
+    /// A define fetched by `ReqwestProvider`, with its own cached client.
+    fn reqwest_define(id: &str, retries: u32) -> ResolverDefine {
+        let mut define = define::all().into_iter().next().unwrap();
+        define.id = id.to_string();
+        define.provider = Some(ContentProviderType::Reqwest);
+        define.http = Some(ResolverHttpDefine {
+            retries: Some(retries),
+            backoff: Some(1),
+            ..ResolverHttpDefine::default()
+        });
+        define
+    }
+
+    #[test]
+    fn test_build_client() {
+        let mut define = define::all().into_iter().next().unwrap();
+        let mut http = ResolverHttpDefine::default();
+        http.headers
+            .insert("Referer".to_string(), "https://example.com/".to_string());
+        http.cookies = Some("uid=1; token=abc".to_string());
+        http.proxy = Some("socks5://127.0.0.1:1080".to_string());
+        define.http = Some(http);
+        assert!(build_client(&define).is_ok());
+
+        define
+            .http
+            .as_mut()
+            .unwrap()
+            .headers
+            .insert("Bad Header".to_string(), "value".to_string());
+        assert!(build_client(&define).is_err());
+    }
+
+    #[async_std::test]
+    async fn test_file_provider() {
+        let define = define::all().into_iter().next().unwrap();
+        let mut provider = FileProvider::new();
+        provider.insert(
+            "https://www.domp4.cc/html/example.html",
+            Path::new("./fixtures/domp4/episodes.html"),
+        );
+
+        let html = provider
+            .get(&define, "https://www.domp4.cc/html/example.html")
+            .await
+            .unwrap();
+        assert!(html.contains("down-list"));
+        assert!(provider
+            .get(&define, "https://www.domp4.cc/missing.html")
+            .await
+            .is_err());
+    }
+
+    #[async_std::test]
+    async fn test_reqwest_retries() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let server = MockServer::start(move |_| match counter.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => MockResponse::new(503, "busy"),
+            _ => MockResponse::new(200, "<html>ok</html>"),
+        });
+        let define = reqwest_define("retry-test", 3);
+
+        let html = ReqwestProvider
+            .get(&define, &format!("{}/tv/1.html", server.url))
+            .await
+            .unwrap();
+
+        assert!(html.contains("ok"));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[async_std::test]
+    async fn test_reqwest_retries_exhausted() {
+        let server = MockServer::start(|_| MockResponse::new(500, ""));
+        let define = reqwest_define("exhausted-test", 2);
+
+        let result = ReqwestProvider
+            .get(&define, &format!("{}/tv/1.html", server.url))
+            .await;
+
+        assert!(result.is_err());
+        // the first attempt and two retries
+        assert_eq!(server.requests().len(), 3);
+    }
+}
@@ -1,27 +1,35 @@
-use std::time::Duration;
+use std::sync::atomic::Ordering;
 
 use anyhow::Result;
+use async_std::task;
 use async_trait::async_trait;
-use headless_chrome::{Browser, Element};
-use log::{error, info};
-use magnet_url::Magnet;
+use futures::stream::{self, StreamExt};
+use log::{error, info, warn};
 use rbatis::crud::CRUD;
-use regex::Regex;
-use reqwest::header;
+use regex::{NoExpand, Regex};
 use scraper::{Html, Selector};
 
 use serde::Deserialize;
 use serde::Serialize;
 use thiserror::Error;
 
+use crate::define::{self, ResolverDefine, ResolverRowsDefine, ResolverSearchDefine};
+use crate::events::{self, Event, EventKind};
 use crate::global;
-use crate::model::{Tv, TvSeed};
-
-#[derive(Debug)]
+use crate::limiter::LIMITER;
+use crate::link;
+use crate::model::{self, Tv, TvSeed};
+use crate::overrides;
+use crate::provider::{self, ContentProvider};
+use crate::sonarr;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Data {
     pub(crate) name: String,
     pub(crate) url: String,
     pub(crate) ep: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) size: Option<u64>,
 }
 
 impl Data {
@@ -30,6 +38,7 @@ impl Data {
             name: name.to_string(),
             url: url.to_string(),
             ep: -1,
+            size: None,
         }
     }
 }
@@ -41,42 +50,174 @@ impl Resolver {
         Resolver {}
     }
 
-    pub async fn fetch_by_tv(&self, tv_id: i64) {
+    /// Fetches shows concurrently, up to `FETCH_CONCURRENCY` at the same time.
+    pub async fn fetch_all(&self, tv_ids: Vec<i64>) {
+        let concurrency = global::FETCH_CONCURRENCY.load(Ordering::Relaxed).max(1);
+        stream::iter(tv_ids)
+            .for_each_concurrent(concurrency, |tv_id| async move {
+                if let Err(e) = self.fetch_by_tv(tv_id).await {
+                    error!("fetch tv:{} failed: {}", tv_id, e);
+                }
+            })
+            .await;
+    }
+
+    pub async fn fetch_by_tv(&self, tv_id: i64) -> Result<()> {
         let wrapper = global::RB.new_wrapper().eq("id", tv_id);
-        let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await.unwrap();
+        let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await?;
 
         if tv.is_some() {
-            let tv = tv.unwrap();
-            let resolver = DefaultResolver::new();
-            let data = resolver.fetch(&tv).await.unwrap();
-            let data = resolver.normalize(&tv, data).await.unwrap();
-
-            info!("find {:?} for tv:{:?}", data, tv);
-
-            if data.len() > 0 {
-                let wrapper = global::RB.new_wrapper().eq("tv_id", tv.id.unwrap());
-                let delete_count = global::RB
-                    .remove_by_wrapper::<TvSeed>(wrapper)
-                    .await
-                    .unwrap();
-                info!("delete seed for tv count:{}", delete_count);
-
-                let tv_id = tv.id.unwrap();
-                for d in data {
-                    let seed = TvSeed {
-                        id: None,
-                        tv_id: Some(tv_id),
-                        ep: Some(d.ep),
-                        url: Some(d.url),
-                        name: Some(d.name),
-                    };
-                    global::RB.save(&seed, &[]).await;
-                }
+            let mut tv = tv.unwrap();
+            if let Err(e) = self.fetch_tv(&mut tv).await {
+                events::emit(Event {
+                    tv_id: tv.id,
+                    tv_name: tv.name.clone(),
+                    url: tv.url.clone(),
+                    error: Some(e.to_string()),
+                    ..Event::new(EventKind::FetchFailed)
+                });
+                return Err(e);
             }
         } else {
             log::error!("found find movie with id:{}", tv_id);
         }
+        Ok(())
     }
+
+    async fn fetch_tv(&self, tv: &mut Tv) -> Result<()> {
+        let resolver = DefaultResolver::new();
+
+        if tv.url.as_ref().map_or(true, |url| url.is_empty()) {
+            let url = resolver.discover(tv).await?;
+            info!("discover url:{} for tv:{:?}", url, tv);
+            tv.url = Some(url);
+            global::RB.update_by_column(Tv::id(), tv).await?;
+        }
+
+        let data = resolver.fetch(tv).await?;
+        if data.is_empty() {
+            let url = tv.url.clone().unwrap_or_default();
+            events::emit(Event {
+                tv_id: tv.id,
+                tv_name: tv.name.clone(),
+                define: resolver.find_define(&url).map(|d| d.id.clone()),
+                url: Some(url),
+                ..Event::new(EventKind::DefineBroken)
+            });
+        }
+        let data = resolver.normalize(tv, data).await?;
+
+        info!("find {:?} for tv:{:?}", data, tv);
+
+        if data.len() > 0 {
+            let tv_id = tv.id.unwrap();
+            let wrapper = global::RB.new_wrapper().eq("tv_id", tv_id);
+            let old: Vec<TvSeed> = global::RB.fetch_list_by_wrapper(wrapper).await?;
+
+            let overrides = overrides::load(tv_id).await?;
+
+            // seeds added by hand survive scrapes
+            let wrapper = global::RB
+                .new_wrapper()
+                .eq("tv_id", tv_id)
+                .and()
+                .is_null(TvSeed::manual());
+            let delete_count = global::RB.remove_by_wrapper::<TvSeed>(wrapper).await?;
+            info!("delete seed for tv count:{}", delete_count);
+
+            let mut discovered = vec![];
+            for d in data {
+                let info_hash = link::info_hash(&d.url);
+                let manual = old.iter().any(|seed| {
+                    seed.manual.unwrap_or(false)
+                        && info_hash.is_some()
+                        && seed.info_hash == info_hash
+                });
+                if manual {
+                    continue;
+                }
+
+                let seed = TvSeed {
+                    id: None,
+                    tv_id: Some(tv_id),
+                    ep: Some(d.ep),
+                    url: Some(d.url),
+                    name: Some(d.name),
+                    size: d.size.map(|size| size as i64),
+                    info_hash,
+                    season: None,
+                    pinned: None,
+                    manual: None,
+                };
+                let tvname = series_name(tv);
+                let seed = match overrides::apply(tvname, &overrides, seed) {
+                    Some(seed) => seed,
+                    None => continue,
+                };
+
+                if !old.iter().any(|old| old.url == seed.url) {
+                    discovered.push((seed.season, seed.ep.unwrap_or(-1)));
+                    events::emit(Event {
+                        tv_id: Some(tv_id),
+                        tv_name: tv.name.clone(),
+                        ep: seed.ep,
+                        seed_name: seed.name.clone(),
+                        url: seed.url.clone(),
+                        ..Event::new(EventKind::SeedDiscovered)
+                    });
+                }
+                global::RB.save(&seed, &[]).await?;
+            }
+
+            if !discovered.is_empty() {
+                tv.updated_at = Some(model::timestamp());
+                global::RB.update_by_column(Tv::id(), tv).await?;
+
+                let tv = tv.clone();
+                task::spawn(async move { sonarr::search(&tv, &discovered).await });
+            }
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref RELEASE_EPISODE: Regex = Regex::new(r" S\d+E\d+ - \d+ - ").unwrap();
+}
+
+/// The series name Sonarr knows the show by, the display name when it is missing.
+pub fn series_name(tv: &Tv) -> &str {
+    tv.tvname
+        .as_deref()
+        .or(tv.name.as_deref())
+        .unwrap_or_default()
+}
+
+/// The seed name Sonarr parses, like `Show S01E3 - 3 - [chinese] - 剧名 第03集 - Wkavu`.
+pub fn release_name(tvname: &str, season: Option<i64>, ep: i64, title: &str) -> String {
+    format!(
+        "{} S{:02}E{} - {} - [chinese] - {} - Wkavu",
+        tvname,
+        season.unwrap_or(1),
+        ep,
+        ep,
+        title
+    )
+}
+
+/// Puts a changed season or episode into the seed's name, so the change reaches Sonarr.
+pub fn rename(tvname: &str, seed: &mut TvSeed) {
+    let (name, ep) = match (&seed.name, seed.ep) {
+        (Some(name), Some(ep)) if ep > 0 => (name, ep),
+        _ => return,
+    };
+    let marker = format!(" S{:02}E{} - {} - ", seed.season.unwrap_or(1), ep, ep);
+    let name = if RELEASE_EPISODE.is_match(name) {
+        RELEASE_EPISODE.replace(name, NoExpand(&marker)).to_string()
+    } else {
+        release_name(tvname, seed.season, ep, name)
+    };
+    seed.name = Some(name);
 }
 
 fn extra_ep(name: &str) -> Result<i64> {
@@ -116,6 +257,7 @@ fn extra_ep(name: &str) -> Result<i64> {
 #[async_trait]
 trait CommonResolver {
     fn new() -> Self;
+    async fn discover(&self, tv: &Tv) -> Result<String>;
     async fn fetch(&self, tv: &Tv) -> Result<Vec<Data>>;
     async fn normalize(&self, tv: &Tv, datas: Vec<Data>) -> Result<Vec<Data>>;
 }
@@ -124,217 +266,391 @@ trait CommonResolver {
 pub enum ResolveError {
     #[error("Can't parse ep for name: {0}")]
     EpParseFailure(String),
+    #[error("Can't find define for url: {0}")]
+    DefineNotFound(String),
+    #[error("Can't discover page for tv: {0}")]
+    DiscoverFailure(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ResolverDefine {
-    id: String,
-    name: String,
-    domains: Vec<String>,
-    timeout: u64,
-    search: ResolverSearchDefine,
-    provider: Option<ContentProviderType>,
+pub struct DefaultResolver {
+    pub defines: Vec<ResolverDefine>,
+    /// overrides the provider of every define when set
+    provider: Option<Box<dyn ContentProvider>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum ContentProviderType {
-    Chrome,
-    Reqwest,
-}
+impl DefaultResolver {
+    pub fn with_provider(provider: Box<dyn ContentProvider>) -> Self {
+        DefaultResolver {
+            defines: define::all(),
+            provider: Some(provider),
+        }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ResolverRowSelectorDefine {
-    attr: Option<String>,
+    fn find_define(&self, url: &str) -> Option<&ResolverDefine> {
+        self.defines
+            .iter()
+            .find(|d| d.domains.iter().any(|domain| url.starts_with(domain)))
+    }
+
+    async fn fetch_html(&self, define: &ResolverDefine, url: &str) -> Result<String> {
+        let provider: &dyn ContentProvider = match &self.provider {
+            Some(provider) => provider.as_ref(),
+            None => provider::for_define(define),
+        };
+        let _permit = LIMITER.acquire(define).await;
+        provider.get(define, url).await
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ResolverRowsDefine {
-    selector: String,
-    title: ResolverRowSelectorDefine,
-    url: ResolverRowSelectorDefine,
+/// The define used to render a search results page, without the show page's `wait`
+/// selector and actions which never match there.
+fn results_page(define: &ResolverDefine) -> ResolverDefine {
+    ResolverDefine {
+        search: ResolverSearchDefine {
+            wait: None,
+            actions: None,
+            ..define.search.clone()
+        },
+        ..define.clone()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ResolverSearchDefine {
-    wait: Option<String>,
-    rows: ResolverRowsDefine,
+/// Names used to search a show, the display name first and aliases last.
+fn search_names(tv: &Tv) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    let aliases = tv.aliases.as_deref().unwrap_or("");
+    let candidates = [tv.name.as_deref(), tv.tvname.as_deref()]
+        .into_iter()
+        .flatten()
+        .chain(aliases.split(','));
+    for name in candidates {
+        let name = name.trim();
+        if !name.is_empty() && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
 }
 
-#[derive(RustEmbed)]
-#[folder = "define/"]
-struct Define;
+/// Rows matched on a page, skipping rows without a title or the configured url attribute.
+fn select_rows(
+    html_content: &str,
+    define_id: &str,
+    rows: &ResolverRowsDefine,
+) -> Result<Vec<Data>> {
+    let document = Html::parse_document(html_content);
+    info!("get doc object");
+
+    let mut data = vec![];
+    let selector = Selector::parse(&rows.selector).unwrap();
+    let list = document.select(&selector);
+
+    for item in list {
+        let title = if let Some(attr) = &rows.title.attr {
+            item.value().attr(attr)
+        } else {
+            item.text().next()
+        };
+        let url = rows
+            .url
+            .attr
+            .as_ref()
+            .and_then(|attr| item.value().attr(attr));
+
+        match (title, url) {
+            (Some(title), Some(url)) => data.push(Data::new(title, url)),
+            _ => warn!(
+                "define {} skips a row without title or url: {}",
+                define_id,
+                item.html()
+            ),
+        }
+    }
 
-pub struct DefaultResolver {
-    pub defines: Vec<ResolverDefine>,
+    Ok(data)
 }
 
 #[async_trait]
 impl CommonResolver for DefaultResolver {
     fn new() -> Self {
-        let mut defines = vec![];
-        for file in Define::iter() {
-            let yaml = Define::get(file.as_ref()).unwrap();
-            let yaml_content = std::str::from_utf8(yaml.data.as_ref());
-            let define: ResolverDefine = serde_yaml::from_str(yaml_content.unwrap()).unwrap();
-            info!("load config for {}", define.id);
-            defines.push(define);
+        DefaultResolver {
+            defines: define::all(),
+            provider: None,
         }
-        DefaultResolver { defines }
     }
 
-    async fn fetch(&self, tv: &Tv) -> Result<Vec<Data>> {
-        let url = tv.url.as_ref().unwrap();
-        let selected_define = self.defines.iter().find(|d| {
-            for domain in &d.domains {
-                if url.starts_with(domain) {
-                    return true;
-                }
-            }
-            return false;
-        });
-        let selected_define = selected_define.unwrap();
+    async fn discover(&self, tv: &Tv) -> Result<String> {
+        let names = search_names(tv);
 
-        info!("starting fetch...");
-        let mut data = vec![];
+        for define in &self.defines {
+            let (search_url, results) = match (&define.search.url, &define.search.results) {
+                (Some(search_url), Some(results)) => (search_url, results),
+                _ => continue,
+            };
 
-        let provider_type = selected_define
-            .provider
-            .as_ref()
-            .unwrap_or(&ContentProviderType::Chrome);
-
-        let html_content = match provider_type {
-            ContentProviderType::Reqwest => {
-                let mut headers = header::HeaderMap::new();
-                headers.insert(
-                    header::USER_AGENT,
-                    header::HeaderValue::from_static("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36"),
-                );
-
-                // get a client builder
-                let client = reqwest::Client::builder()
-                    .default_headers(headers)
-                    .build()?;
-                let res = client.get(url).send().await?;
-                res.text().await?
+            for name in &names {
+                let url = search_url.replace("{name}", &urlencoding::encode(name));
+                info!("search {} with url:{}", name, url);
+
+                let html_content = self.fetch_html(&results_page(define), &url).await?;
+                let rows = select_rows(&html_content, &define.id, results)?;
+
+                let matched = rows
+                    .into_iter()
+                    .find(|d| d.name.to_lowercase().contains(&name.to_lowercase()));
+                if let Some(matched) = matched {
+                    let page_url = reqwest::Url::parse(&url)?.join(&matched.url)?;
+                    if define
+                        .domains
+                        .iter()
+                        .any(|domain| page_url.as_str().starts_with(domain))
+                    {
+                        return Ok(page_url.to_string());
+                    }
+                }
             }
-            ContentProviderType::Chrome => {
-                let browser = Browser::default().unwrap();
+        }
 
-                let tab = browser.wait_for_initial_tab().unwrap();
-                info!("browser tab is ready");
+        Err(ResolveError::DiscoverFailure(tv.tvdbid.clone().unwrap_or_default()).into())
+    }
 
-                tab.navigate_to(&url).unwrap();
+    async fn fetch(&self, tv: &Tv) -> Result<Vec<Data>> {
+        let url = tv.url.as_ref().unwrap();
+        let selected_define = self
+            .find_define(url)
+            .ok_or_else(|| ResolveError::DefineNotFound(url.to_string()))?;
 
-                selected_define.search.wait.as_ref().map(|wait| {
-                    info!("waiting for special button");
-                    tab.wait_for_element_with_custom_timeout(
-                        &wait,
-                        Duration::from_secs(selected_define.timeout),
-                    )
-                    .unwrap();
-                });
+        info!("starting fetch...");
+        let html_content = self.fetch_html(selected_define, url).await?;
 
-                let root_div: Element = tab.wait_for_element("body").unwrap();
-                let html = root_div
-                    .call_js_fn("function() { return this.innerHTML;}", true)
-                    .unwrap()
-                    .value
-                    .unwrap();
+        select_rows(
+            &html_content,
+            &selected_define.id,
+            &selected_define.search.rows,
+        )
+    }
 
-                html.as_str().unwrap().to_string()
+    async fn normalize(&self, tv: &Tv, datas: Vec<Data>) -> Result<Vec<Data>> {
+        let page_url = tv.url.as_deref().unwrap_or_default();
+        let define = self.find_define(page_url);
+        let mut result = vec![];
+
+        for d in datas {
+            if extra_ep(&d.name).is_err() {
+                error!("can't parse {}", &d.name);
+                continue;
             }
-        };
 
-        let document = Html::parse_document(&html_content);
-        info!("get doc object");
+            let link = match link::resolve(define, page_url, &d.url).await {
+                Ok(link) => link,
+                Err(e) => {
+                    error!("can't resolve link {} of {}: {}", &d.url, &d.name, e);
+                    continue;
+                }
+            };
+
+            let clean_up_name = str::replace(
+                &str::replace(&d.name, "HD1080p", "[HDTV-1080p]"),
+                ".mp4",
+                "",
+            );
 
-        let selector = Selector::parse(&selected_define.search.rows.selector).unwrap();
-        let list = document.select(&selector);
+            let ep = extra_ep(&clean_up_name).expect("can't extra ep");
 
-        for item in list {
-            let title = if let Some(attr) = &selected_define.search.rows.title.attr {
-                item.value().attr(attr)
+            let clean_up_name = if ep > 0 {
+                release_name(series_name(tv), None, ep, &clean_up_name)
             } else {
-                item.text().next()
+                clean_up_name
             };
-            let url = selected_define
-                .search
-                .rows
-                .url
-                .attr
-                .as_ref()
-                .map(|attr| item.value().attr(attr));
-
-            data.push(Data::new(title.unwrap(), url.unwrap().unwrap()));
-        }
 
-        Ok(data)
-    }
-
-    async fn normalize(&self, tv: &Tv, datas: Vec<Data>) -> Result<Vec<Data>> {
-        Ok(datas
-            .into_iter()
-            .filter(|d| {
-                let enable_to_parse = extra_ep(&d.name).is_ok();
-                if !enable_to_parse {
-                    error!("can't parse {}", &d.name);
-                }
-                enable_to_parse
-            })
-            .map(|d| {
-                let clean_up_name = str::replace(
-                    &str::replace(&d.name, "HD1080p", "[HDTV-1080p]"),
-                    ".mp4",
-                    "",
-                );
-
-                let mut magneturl = Magnet::new(&d.url).unwrap();
-                magneturl.tr.clear();
-                magneturl.dn = None;
-
-                let ep = extra_ep(&clean_up_name).expect("can't extra ep");
-
-                let clean_up_name = if ep > 0 {
-                    format!(
-                        "{} S01E{} - {} - [chinese] - {} - Wkavu",
-                        tv.tvname.as_ref().unwrap(),
-                        ep,
-                        ep,
-                        &clean_up_name
-                    )
-                } else {
-                    clean_up_name
-                };
+            result.push(Data {
+                ep,
+                name: clean_up_name,
+                url: link.magnet,
+                size: link.size,
+            });
+        }
 
-                Data {
-                    ep,
-                    name: clean_up_name,
-                    url: magneturl.to_string(),
-                }
-            })
-            .collect())
+        Ok(result)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::fs;
+    use std::path::Path;
+
+    use async_std::task;
 
     use super::*;
+    use crate::define::BrowserAction;
+    use crate::provider::FileProvider;
+
+    /// Saved page of a define with the page url and the expected normalized rows.
+    #[derive(Serialize, Deserialize)]
+    struct Fixture {
+        url: String,
+        tvname: String,
+        data: Vec<Data>,
+    }
+
+    /// Feeds `fixtures/<define id>/<case>.html` through fetch and normalize, and compares
+    /// with `<case>.json`. Set `UPDATE_FIXTURES` to rewrite the expected rows instead.
+    /// Returns the define id of every passed case.
+    fn run_fixtures(folder: &Path) -> Vec<String> {
+        let mut provider = FileProvider::new();
+        let mut cases = vec![];
+
+        for define_folder in fs::read_dir(folder).unwrap() {
+            let define_folder = define_folder.unwrap().path();
+            if !define_folder.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&define_folder).unwrap() {
+                let html_path = file.unwrap().path();
+                if html_path.extension().map_or(true, |ext| ext != "html") {
+                    continue;
+                }
+                let json_path = html_path.with_extension("json");
+                let fixture: Fixture =
+                    serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+                provider.insert(&fixture.url, &html_path);
+                cases.push((define_folder.clone(), html_path, json_path, fixture));
+            }
+        }
+
+        let resolver = DefaultResolver::with_provider(Box::new(provider));
+        let mut passed = vec![];
+
+        for (define_folder, html_path, json_path, mut fixture) in cases {
+            let id = define_folder.file_name().unwrap().to_string_lossy();
+            let define = resolver
+                .defines
+                .iter()
+                .find(|d| d.id == id)
+                .unwrap_or_else(|| panic!("no define for fixture folder {:?}", define_folder));
+            assert_eq!(
+                resolver.find_define(&fixture.url).map(|d| &d.id),
+                Some(&define.id),
+                "fixture url {} doesn't match define {}",
+                fixture.url,
+                define.id
+            );
+
+            let tv = Tv {
+                id: Some(1),
+                tvdbid: Some("1".to_string()),
+                tvname: Some(fixture.tvname.to_string()),
+                url: Some(fixture.url.to_string()),
+                name: Some(fixture.tvname.to_string()),
+                aliases: None,
+                updated_at: None,
+                sonarr_id: None,
+                removed: None,
+            };
+            let data = task::block_on(resolver.fetch(&tv)).unwrap();
+            let data = task::block_on(resolver.normalize(&tv, data)).unwrap();
+
+            if std::env::var("UPDATE_FIXTURES").is_ok() {
+                fixture.data = data;
+                let json = serde_json::to_string_pretty(&fixture).unwrap();
+                fs::write(&json_path, json + "\n").unwrap();
+            } else {
+                assert_eq!(data, fixture.data, "fixture {:?}", html_path);
+            }
+            passed.push(define.id.to_string());
+        }
+
+        passed
+    }
 
     #[test]
-    fn test_parse_yml() {
-        let paths = fs::read_dir("./define").unwrap();
-
-        for path in paths {
-            let result = std::fs::read_to_string(path.unwrap().path()).unwrap();
-            let define: ResolverDefine = serde_yaml::from_str(&result).unwrap();
-            println!("Define:{:?}", define);
-            // Ensure that the define is parsed successfully
-            assert!(define.name.len() > 0);
+    fn test_fixtures() {
+        let passed = run_fixtures(Path::new("./fixtures"));
+        // Ensure that every embedded define ships at least one passing fixture
+        for define in DefaultResolver::new().defines {
+            assert!(
+                passed.contains(&define.id),
+                "no fixture for define {}",
+                define.id
+            );
         }
     }
 
+    #[test]
+    fn test_rename() {
+        let mut seed = TvSeed {
+            id: None,
+            tv_id: Some(1),
+            ep: Some(12),
+            url: None,
+            name: Some(release_name("Show", None, 3, "剧名 第03集")),
+            size: None,
+            info_hash: None,
+            season: Some(2),
+            pinned: None,
+            manual: None,
+        };
+        rename("Show", &mut seed);
+        assert_eq!(
+            seed.name.as_deref(),
+            Some("Show S02E12 - 12 - [chinese] - 剧名 第03集 - Wkavu")
+        );
+
+        seed.name = Some("added by hand".to_string());
+        rename("Show", &mut seed);
+        assert_eq!(
+            seed.name.as_deref(),
+            Some("Show S02E12 - 12 - [chinese] - added by hand - Wkavu")
+        );
+    }
+
+    #[test]
+    fn test_results_page() {
+        let mut define = define::all().into_iter().next().unwrap();
+        define.search.wait = Some("div.down-list".to_string());
+        define.search.actions = Some(vec![BrowserAction::ScrollBottom]);
+
+        let search = results_page(&define);
+        assert!(search.search.wait.is_none());
+        assert!(search.search.actions.is_none());
+        assert_eq!(search.search.rows.selector, define.search.rows.selector);
+        assert_eq!(search.id, define.id);
+    }
+
+    #[test]
+    fn test_search_names() {
+        let tv = Tv {
+            id: None,
+            tvdbid: Some("1".to_string()),
+            tvname: Some("Show".to_string()),
+            url: None,
+            name: Some("剧名".to_string()),
+            aliases: Some("别名, Show ,".to_string()),
+            updated_at: None,
+            sonarr_id: None,
+            removed: None,
+        };
+
+        assert_eq!(search_names(&tv), vec!["剧名", "Show", "别名"]);
+    }
+
+    #[test]
+    fn test_select_rows() {
+        let rows: ResolverRowsDefine = serde_yaml::from_str(
+            "selector: ul.result a\ntitle:\n  attr: title\nurl:\n  attr: href\n",
+        )
+        .unwrap();
+        let html = r#"<ul class="result"><a title="剧名" href="/tv/1.html">剧名</a>
+            <a title="预告">预告</a><a href="/tv/2.html">无名</a></ul>"#;
+
+        let data = select_rows(html, "test", &rows).unwrap();
+
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].name, "剧名");
+        assert_eq!(data[0].url, "/tv/1.html");
+    }
+
     #[test]
     fn test_load() {
         let resolver = DefaultResolver::new();
@@ -0,0 +1,283 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::RwLock;
+
+use actix_cors::Cors;
+use actix_web::dev::Service;
+use actix_web::http::header;
+use actix_web::{web, App, HttpServer};
+use futures::future::{ready, Either};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::auth;
+use crate::error::AppError;
+use crate::http;
+
+pub const DEFAULT_PORT: u16 = 8000;
+
+lazy_static! {
+    static ref BASE_PATH: RwLock<String> = RwLock::new(String::new());
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// addresses to listen on, with or without a port
+    #[serde(rename = "bind")]
+    pub binds: Vec<String>,
+    pub port: u16,
+    /// listens on `::` instead of `0.0.0.0` when no address is given
+    pub ipv6: bool,
+    pub unix_socket: Option<String>,
+    /// prefix of every route, like `/wkavu` behind a reverse proxy
+    pub base_path: String,
+    pub static_folder: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            binds: vec![],
+            port: DEFAULT_PORT,
+            ipv6: false,
+            unix_socket: None,
+            base_path: String::new(),
+            static_folder: "./webapp".to_string(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// `host:port` of every tcp address, none when only a unix socket is asked for.
+    pub fn addresses(&self) -> Vec<String> {
+        let binds = match (self.binds.is_empty(), &self.unix_socket) {
+            (false, _) => self.binds.clone(),
+            (true, Some(_)) => return vec![],
+            (true, None) if self.ipv6 => vec!["::".to_string()],
+            (true, None) => vec!["0.0.0.0".to_string()],
+        };
+        binds
+            .iter()
+            .map(|bind| {
+                if bind.parse::<SocketAddr>().is_ok() {
+                    bind.clone()
+                } else if let Ok(ip) = bind.parse::<IpAddr>() {
+                    SocketAddr::new(ip, self.port).to_string()
+                } else if bind.contains(':') {
+                    bind.clone()
+                } else {
+                    format!("{}:{}", bind, self.port)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Turns `wkavu/` or `/wkavu` into `/wkavu`, and `/` into an empty prefix.
+pub fn normalize_base_path(path: &str) -> String {
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// The route prefix the server runs under, empty at the root.
+pub fn base_path() -> String {
+    BASE_PATH.read().unwrap().clone()
+}
+
+/// Answers malformed bodies, paths and queries like the handlers' own errors.
+fn extractor_errors(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(
+        web::JsonConfig::default().error_handler(|e, _| AppError::BadRequest(e.to_string()).into()),
+    )
+    .app_data(
+        web::PathConfig::default().error_handler(|e, _| AppError::BadRequest(e.to_string()).into()),
+    )
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|e, _| AppError::BadRequest(e.to_string()).into()),
+    );
+}
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    extractor_errors(cfg);
+    cfg.route("", web::get().to(http::root))
+        .route("/", web::get().to(http::root))
+        .route("/health", web::get().to(http::health))
+        .route("/openapi.json", web::get().to(http::openapi))
+        .route("/api", web::get().to(http::api))
+        .route("/download/{seed_id}", web::get().to(http::download))
+        .route("/admin/fetch", web::get().to(http::refresh))
+        .route("/admin/sonarr/sync", web::post().to(http::sonarr_sync))
+        .route("/admin/defines/reload", web::get().to(http::define_reload))
+        .route("/admin/tvs", web::get().to(http::tv_list))
+        .route("/admin/seeds/tvid/{tvid}", web::get().to(http::seed_list))
+        .route(
+            "/admin/seeds/{seed_id}/push",
+            web::post().to(http::seed_push),
+        )
+        .route("/admin/tvs", web::post().to(http::tv_add))
+        .route("/admin/tvs/delete", web::post().to(http::tv_delete))
+        .route("/admin/tvs/export", web::get().to(http::library_export))
+        .route("/admin/tvs/import", web::post().to(http::library_import))
+        .route("/admin/tvs/{id}", web::get().to(http::tv_get))
+        .route("/admin/tvs/{id}", web::put().to(http::tv_update))
+        .route("/admin/tvs/{id}", web::patch().to(http::tv_patch))
+        .route("/admin/tvs/{id}", web::delete().to(http::tv_remove))
+        .route("/admin/tvs/{id}/seeds", web::post().to(http::seed_add))
+        .route(
+            "/admin/tvs/{id}/overrides",
+            web::get().to(http::override_list),
+        )
+        .route(
+            "/admin/seeds/{seed_id}/block",
+            web::post().to(http::seed_block),
+        )
+        .route("/admin/seeds/{seed_id}", web::patch().to(http::seed_update))
+        .route(
+            "/admin/seeds/{seed_id}",
+            web::delete().to(http::seed_remove),
+        )
+        .route(
+            "/admin/overrides/{id}",
+            web::delete().to(http::override_delete),
+        );
+}
+
+pub async fn run(config: ServerConfig) -> std::io::Result<()> {
+    *BASE_PATH.write().unwrap() = config.base_path.clone();
+
+    let base_path = config.base_path.clone();
+    let static_folder = config.static_folder.clone();
+    let mut server = HttpServer::new(move || {
+        let prefix = base_path.clone();
+        App::new()
+            .wrap_fn(move |req, srv| {
+                let path = req.path().strip_prefix(prefix.as_str()).unwrap_or("");
+                let authorization = req
+                    .headers()
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok());
+                let rejected = auth::reject(path, req.query_string(), authorization);
+                match rejected {
+                    Some(response) => Either::Right(ready(Ok(req.into_response(response)))),
+                    None => Either::Left(srv.call(req)),
+                }
+            })
+            .wrap(Cors::permissive())
+            .service(
+                web::scope(&base_path)
+                    .configure(routes)
+                    .service(actix_files::Files::new("/", &static_folder).index_file("index.html")),
+            )
+    });
+
+    for address in config.addresses() {
+        info!("listening on {}{}", address, config.base_path);
+        server = server.bind(&address)?;
+    }
+    #[cfg(unix)]
+    if let Some(socket) = &config.unix_socket {
+        // a socket left by an earlier run would fail the bind
+        let _ = std::fs::remove_file(socket);
+        info!("listening on unix:{}{}", socket, config.base_path);
+        server = server.bind_uds(socket)?;
+    }
+
+    server.run().await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test};
+
+    use super::*;
+
+    #[test]
+    fn test_addresses() {
+        let config = ServerConfig::default();
+        assert_eq!(config.addresses(), vec!["0.0.0.0:8000"]);
+
+        let config = ServerConfig {
+            ipv6: true,
+            port: 9000,
+            ..ServerConfig::default()
+        };
+        assert_eq!(config.addresses(), vec!["[::]:9000"]);
+
+        let config = ServerConfig {
+            binds: vec![
+                "127.0.0.1".to_string(),
+                "::1".to_string(),
+                "[::1]:9001".to_string(),
+                "localhost".to_string(),
+            ],
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            config.addresses(),
+            vec![
+                "127.0.0.1:8000",
+                "[::1]:8000",
+                "[::1]:9001",
+                "localhost:8000"
+            ]
+        );
+
+        let config = ServerConfig {
+            unix_socket: Some("/run/wkavu.sock".to_string()),
+            ..ServerConfig::default()
+        };
+        assert!(config.addresses().is_empty());
+    }
+
+    #[test]
+    fn test_normalize_base_path() {
+        assert_eq!(normalize_base_path(""), "");
+        assert_eq!(normalize_base_path("/"), "");
+        assert_eq!(normalize_base_path("wkavu/"), "/wkavu");
+        assert_eq!(normalize_base_path("/apps/wkavu"), "/apps/wkavu");
+    }
+
+    #[actix_rt::test]
+    async fn test_scope() {
+        let mut app =
+            test::init_service(App::new().service(web::scope("/wkavu").configure(routes))).await;
+
+        let req = test::TestRequest::get().uri("/wkavu/health").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri("/wkavu").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_extractor_errors() {
+        let mut app = test::init_service(App::new().configure(routes)).await;
+
+        let req = test::TestRequest::get().uri("/admin/tvs/abc").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], 1);
+
+        let req = test::TestRequest::post()
+            .uri("/admin/tvs")
+            .header(header::CONTENT_TYPE, "application/json")
+            .set_payload("{\"name\":")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], 1);
+    }
+}
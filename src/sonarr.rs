@@ -0,0 +1,351 @@
+use std::sync::RwLock;
+
+use anyhow::Result;
+use log::{info, warn};
+use rbatis::crud::CRUD;
+use serde::{Deserialize, Serialize};
+
+use crate::global;
+use crate::model::{self, Tv};
+
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+lazy_static! {
+    static ref SONARR: RwLock<Option<SonarrConfig>> = RwLock::new(None);
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SonarrConfig {
+    pub url: String,
+    pub api_key: String,
+    /// creates shows for new monitored series instead of only proposing them
+    pub auto_create: bool,
+    /// asks Sonarr to search episodes wkavu found new seeds for
+    pub search: bool,
+}
+
+pub fn init(config: Option<SonarrConfig>) {
+    if let Some(config) = &config {
+        info!("sonarr at {}", config.url);
+    }
+    *SONARR.write().unwrap() = config;
+}
+
+pub fn config() -> Option<SonarrConfig> {
+    SONARR.read().unwrap().clone()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, schemars::JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Series {
+    pub id: i64,
+    pub title: String,
+    pub tvdb_id: i64,
+    #[serde(default)]
+    pub monitored: bool,
+    #[serde(default)]
+    pub alternate_titles: Vec<AlternateTitle>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, schemars::JsonSchema)]
+pub struct AlternateTitle {
+    pub title: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Episode {
+    pub id: i64,
+    pub season_number: i64,
+    pub episode_number: i64,
+}
+
+/// Sonarr API v3.
+pub struct SonarrClient {
+    url: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl SonarrClient {
+    pub fn new(url: &str, api_key: &str) -> Self {
+        SonarrClient {
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn series(&self) -> Result<Vec<Series>> {
+        Ok(self
+            .client
+            .get(format!("{}/api/v3/series", self.url))
+            .header(API_KEY_HEADER, &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    pub async fn episodes(&self, series_id: i64) -> Result<Vec<Episode>> {
+        Ok(self
+            .client
+            .get(format!("{}/api/v3/episode", self.url))
+            .query(&[("seriesId", series_id)])
+            .header(API_KEY_HEADER, &self.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    pub async fn episode_search(&self, episode_ids: &[i64]) -> Result<()> {
+        self.client
+            .post(format!("{}/api/v3/command", self.url))
+            .header(API_KEY_HEADER, &self.api_key)
+            .json(&serde_json::json!({ "name": "EpisodeSearch", "episodeIds": episode_ids }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Default, PartialEq, schemars::JsonSchema)]
+pub struct SyncReport {
+    pub dry_run: bool,
+    /// monitored series without a show, created unless a dry run or proposal
+    pub created: Vec<Series>,
+    /// shows newly linked to their series
+    pub linked: Vec<String>,
+    /// tvdbids of linked shows no longer monitored in Sonarr
+    pub removed: Vec<String>,
+}
+
+/// Compares the shows with Sonarr's monitored series, returning the shows to update.
+pub fn plan(existing: &[Tv], series: &[Series]) -> (SyncReport, Vec<Tv>) {
+    let monitored: Vec<&Series> = series.iter().filter(|s| s.monitored).collect();
+    let mut report = SyncReport::default();
+    let mut updates = vec![];
+
+    for s in &monitored {
+        let tvdbid = s.tvdb_id.to_string();
+        match existing
+            .iter()
+            .find(|tv| tv.tvdbid.as_deref() == Some(tvdbid.as_str()))
+        {
+            Some(tv) => {
+                if tv.sonarr_id != Some(s.id) || tv.removed.unwrap_or(false) {
+                    let mut tv = tv.clone();
+                    tv.sonarr_id = Some(s.id);
+                    tv.removed = None;
+                    report.linked.push(tvdbid);
+                    updates.push(tv);
+                }
+            }
+            None => report.created.push((*s).clone()),
+        }
+    }
+
+    for tv in existing {
+        let linked = tv.sonarr_id.is_some() && !tv.removed.unwrap_or(false);
+        let tvdbid = tv.tvdbid.clone().unwrap_or_default();
+        if linked && !monitored.iter().any(|s| s.tvdb_id.to_string() == tvdbid) {
+            let mut tv = tv.clone();
+            tv.removed = Some(true);
+            report.removed.push(tvdbid);
+            updates.push(tv);
+        }
+    }
+
+    (report, updates)
+}
+
+fn to_tv(series: &Series) -> Tv {
+    let aliases: Vec<&str> = series
+        .alternate_titles
+        .iter()
+        .map(|t| t.title.as_str())
+        .filter(|title| *title != series.title)
+        .collect();
+    Tv {
+        id: None,
+        tvdbid: Some(series.tvdb_id.to_string()),
+        tvname: Some(series.title.clone()),
+        url: None,
+        name: Some(series.title.clone()),
+        aliases: Some(aliases.join(",")).filter(|aliases| !aliases.is_empty()),
+        updated_at: Some(model::timestamp()),
+        sonarr_id: Some(series.id),
+        removed: None,
+    }
+}
+
+/// Links shows to Sonarr series, marks unmonitored ones removed and creates missing ones
+/// when `create` is set.
+pub async fn sync(config: &SonarrConfig, create: bool, dry_run: bool) -> Result<SyncReport> {
+    let series = SonarrClient::new(&config.url, &config.api_key)
+        .series()
+        .await?;
+    let existing: Vec<Tv> = global::RB.fetch_list().await?;
+    let (mut report, updates) = plan(&existing, &series);
+    report.dry_run = dry_run;
+
+    if !dry_run {
+        for tv in &updates {
+            global::RB.update_by_column(Tv::id(), tv).await?;
+        }
+        if create {
+            for series in &report.created {
+                global::RB.save(&to_tv(series), &[]).await?;
+            }
+        }
+    }
+    info!(
+        "sonarr sync created:{} linked:{} removed:{}",
+        report.created.len(),
+        report.linked.len(),
+        report.removed.len()
+    );
+    Ok(report)
+}
+
+/// Asks Sonarr to search the episodes of a show that got new seeds.
+pub async fn search(tv: &Tv, episodes: &[(Option<i64>, i64)]) {
+    let config = match config() {
+        Some(config) if config.search => config,
+        _ => return,
+    };
+    let series_id = match tv.sonarr_id {
+        Some(series_id) if !tv.removed.unwrap_or(false) => series_id,
+        _ => return,
+    };
+
+    let client = SonarrClient::new(&config.url, &config.api_key);
+    match search_episodes(&client, series_id, episodes).await {
+        Ok(episode_ids) => info!("sonarr search episodes:{:?}", episode_ids),
+        Err(e) => warn!("sonarr search for tv:{:?} failed: {}", tv.id, e),
+    }
+}
+
+async fn search_episodes(
+    client: &SonarrClient,
+    series_id: i64,
+    episodes: &[(Option<i64>, i64)],
+) -> Result<Vec<i64>> {
+    let episode_ids = match_episodes(&client.episodes(series_id).await?, episodes);
+    if !episode_ids.is_empty() {
+        client.episode_search(&episode_ids).await?;
+    }
+    Ok(episode_ids)
+}
+
+/// Episode ids for `(season, ep)` pairs, any regular season when the season is unknown.
+fn match_episodes(all: &[Episode], episodes: &[(Option<i64>, i64)]) -> Vec<i64> {
+    all.iter()
+        .filter(|e| {
+            episodes.iter().any(|(season, ep)| {
+                e.episode_number == *ep
+                    && match season {
+                        Some(season) => e.season_number == *season,
+                        None => e.season_number > 0,
+                    }
+            })
+        })
+        .map(|e| e.id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+
+    const SERIES: &str = r#"[
+        {"id": 1, "title": "Three-Body", "tvdbId": 421026, "monitored": true,
+         "alternateTitles": [{"title": "三体"}]},
+        {"id": 2, "title": "Breaking Bad", "tvdbId": 81189, "monitored": false}
+    ]"#;
+
+    fn tv(id: i64, tvdbid: &str, sonarr_id: Option<i64>) -> Tv {
+        Tv {
+            id: Some(id),
+            tvdbid: Some(tvdbid.to_string()),
+            tvname: None,
+            url: None,
+            name: None,
+            aliases: None,
+            updated_at: None,
+            sonarr_id,
+            removed: None,
+        }
+    }
+
+    #[async_std::test]
+    async fn test_client() {
+        let server =
+            MockServer::start(
+                |req| match (req.method.as_str(), req.header(API_KEY_HEADER)) {
+                    (_, Some("key")) if req.path == "/api/v3/series" => {
+                        MockResponse::new(200, SERIES)
+                    }
+                    (_, Some("key")) if req.path == "/api/v3/episode?seriesId=1" => {
+                        MockResponse::new(
+                            200,
+                            r#"[{"id": 10, "seasonNumber": 1, "episodeNumber": 3},
+                    {"id": 11, "seasonNumber": 0, "episodeNumber": 3}]"#,
+                        )
+                    }
+                    ("POST", Some("key")) => MockResponse::new(201, "{}"),
+                    _ => MockResponse::new(401, ""),
+                },
+            );
+        let client = SonarrClient::new(&format!("{}/", server.url), "key");
+
+        let series = client.series().await.unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].tvdb_id, 421026);
+        assert_eq!(series[0].alternate_titles[0].title, "三体");
+
+        let episodes = client.episodes(1).await.unwrap();
+        assert_eq!(match_episodes(&episodes, &[(None, 3)]), vec![10]);
+        assert_eq!(match_episodes(&episodes, &[(Some(0), 3)]), vec![11]);
+
+        client.episode_search(&[10]).await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&server.requests().last().unwrap().body).unwrap();
+        assert_eq!(body["name"], "EpisodeSearch");
+        assert_eq!(body["episodeIds"][0], 10);
+
+        assert!(SonarrClient::new(&server.url, "wrong")
+            .series()
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let series: Vec<Series> = serde_json::from_str(SERIES).unwrap();
+
+        let (report, updates) = plan(&[], &series);
+        assert_eq!(report.created.len(), 1);
+        assert!(updates.is_empty());
+        let created = to_tv(&report.created[0]);
+        assert_eq!(created.tvdbid.as_deref(), Some("421026"));
+        assert_eq!(created.aliases.as_deref(), Some("三体"));
+
+        let existing = vec![
+            tv(1, "421026", None),
+            tv(2, "81189", Some(2)),
+            tv(3, "305288", None),
+        ];
+        let (report, updates) = plan(&existing, &series);
+        assert!(report.created.is_empty());
+        assert_eq!(report.linked, vec!["421026"]);
+        assert_eq!(report.removed, vec!["81189"]);
+        assert_eq!(updates[0].sonarr_id, Some(1));
+        assert_eq!(updates[1].removed, Some(true));
+    }
+}
@@ -0,0 +1,228 @@
+use anyhow::Result;
+use thiserror::Error;
+
+/// Nesting allowed in a torrent file, deeper files are rejected.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum TorrentError {
+    #[error("Invalid torrent at byte {0}: {1}")]
+    Invalid(usize, &'static str),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TorrentInfo {
+    /// lowercase hex sha1 of the bencoded info dictionary
+    pub info_hash: String,
+    pub name: Option<String>,
+    /// total length of all files in bytes
+    pub size: u64,
+}
+
+impl TorrentInfo {
+    pub fn magnet(&self) -> String {
+        format!("magnet:?xt=urn:btih:{}", self.info_hash)
+    }
+}
+
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    /// entries with the raw bytes of the whole dictionary
+    Dict(Vec<(&'a [u8], Value<'a>)>, &'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    fn get(&self, key: &str) -> Option<&Value<'a>> {
+        match self {
+            Value::Dict(entries, _) => entries
+                .iter()
+                .find(|(k, _)| *k == key.as_bytes())
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &'static str) -> anyhow::Error {
+        TorrentError::Invalid(self.pos, msg).into()
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| self.error("unexpected end"))
+    }
+
+    fn read_until(&mut self, end: u8) -> Result<&'a str> {
+        let start = self.pos;
+        let len = self.bytes[start..]
+            .iter()
+            .position(|b| *b == end)
+            .ok_or_else(|| self.error("unexpected end"))?;
+        self.pos = start + len + 1;
+        std::str::from_utf8(&self.bytes[start..start + len]).map_err(|_| self.error("bad number"))
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value<'a>> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deep"));
+        }
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                let number = self.read_until(b'e')?;
+                number
+                    .parse()
+                    .map(Value::Int)
+                    .map_err(|_| self.error("bad integer"))
+            }
+            b'l' => {
+                self.pos += 1;
+                let mut list = vec![];
+                while self.peek()? != b'e' {
+                    list.push(self.value(depth + 1)?);
+                }
+                self.pos += 1;
+                Ok(Value::List(list))
+            }
+            b'd' => {
+                let start = self.pos;
+                self.pos += 1;
+                let mut entries = vec![];
+                while self.peek()? != b'e' {
+                    let key = match self.value(depth + 1)? {
+                        Value::Bytes(key) => key,
+                        _ => return Err(self.error("dictionary key is not a string")),
+                    };
+                    entries.push((key, self.value(depth + 1)?));
+                }
+                self.pos += 1;
+                Ok(Value::Dict(entries, &self.bytes[start..self.pos]))
+            }
+            b'0'..=b'9' => {
+                let len: usize = self
+                    .read_until(b':')?
+                    .parse()
+                    .map_err(|_| self.error("bad string length"))?;
+                let start = self.pos;
+                if self.bytes.len() - start < len {
+                    return Err(self.error("string is too long"));
+                }
+                self.pos += len;
+                Ok(Value::Bytes(&self.bytes[start..self.pos]))
+            }
+            _ => Err(self.error("unknown value")),
+        }
+    }
+}
+
+/// Reads the info hash, name and total size of a `.torrent` file.
+pub fn parse(bytes: &[u8]) -> Result<TorrentInfo> {
+    let mut parser = Parser { bytes, pos: 0 };
+    let root = parser.value(0)?;
+    let info = root
+        .get("info")
+        .ok_or_else(|| TorrentError::Invalid(0, "no info dictionary"))?;
+    let raw_info = match info {
+        Value::Dict(_, raw) => raw,
+        _ => return Err(TorrentError::Invalid(0, "info is not a dictionary").into()),
+    };
+
+    let lengths: Vec<i64> = match info.get("files") {
+        Some(Value::List(files)) => files
+            .iter()
+            .filter_map(|file| file.get("length").and_then(Value::int))
+            .collect(),
+        _ => info
+            .get("length")
+            .and_then(Value::int)
+            .into_iter()
+            .collect(),
+    };
+    let mut size: u64 = 0;
+    for length in lengths {
+        let length =
+            u64::try_from(length).map_err(|_| TorrentError::Invalid(0, "negative file length"))?;
+        size = size
+            .checked_add(length)
+            .ok_or(TorrentError::Invalid(0, "total size overflows"))?;
+    }
+    let name = match info.get("name") {
+        Some(Value::Bytes(name)) => Some(String::from_utf8_lossy(name).to_string()),
+        _ => None,
+    };
+
+    Ok(TorrentInfo {
+        info_hash: sha1_smol::Sha1::from(raw_info).digest().to_string(),
+        name,
+        size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_file() {
+        let info = b"d6:lengthi1024e4:name5:a.mp412:piece lengthi16384e6:pieces0:e";
+        let mut torrent = b"d8:announce19:udp://tracker.local4:info".to_vec();
+        torrent.extend_from_slice(info);
+        torrent.push(b'e');
+
+        let parsed = parse(&torrent).unwrap();
+
+        assert_eq!(
+            parsed.info_hash,
+            sha1_smol::Sha1::from(&info[..]).digest().to_string()
+        );
+        assert_eq!(parsed.name.as_deref(), Some("a.mp4"));
+        assert_eq!(parsed.size, 1024);
+        assert!(parsed.magnet().starts_with("magnet:?xt=urn:btih:"));
+    }
+
+    #[test]
+    fn test_multi_file() {
+        let torrent =
+            b"d4:infod5:filesld6:lengthi10e4:pathl1:aeed6:lengthi20e4:pathl1:beee4:name3:diree";
+
+        let parsed = parse(torrent).unwrap();
+
+        assert_eq!(parsed.size, 30);
+        assert_eq!(parsed.name.as_deref(), Some("dir"));
+    }
+
+    #[test]
+    fn test_bad_lengths() {
+        let negative = b"d4:infod6:lengthi-1e4:name1:aee";
+        assert!(parse(negative).is_err());
+
+        let file = format!("d6:lengthi{}e4:pathl1:aee", i64::MAX);
+        let overflow = format!("d4:infod5:filesl{}{}{}e4:name3:diree", file, file, file);
+        let e = parse(overflow.as_bytes()).unwrap_err();
+        assert!(e.to_string().contains("overflows"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse(b"d4:info").is_err());
+        assert!(parse(b"d3:foo3:bare").is_err());
+        assert!(parse(b"<html></html>").is_err());
+    }
+}
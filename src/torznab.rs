@@ -8,9 +8,14 @@ use rbatis::{Page, PageRequest};
 
 use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
 
+use crate::auth;
+use crate::cache;
+use crate::error::Result;
 use crate::global;
 use crate::http::ApiRequest;
+use crate::link;
 use crate::model::{Tv, TvSeed};
+use crate::trackers;
 
 pub struct TorznabProvider {}
 
@@ -64,7 +69,7 @@ impl TorznabProvider {
         .to_string()
     }
 
-    pub async fn search(&self, info: &Query<ApiRequest>) -> String {
+    pub async fn search(&self, info: &Query<ApiRequest>, base_url: &str) -> Result<String> {
         let wrapper;
         if info.tvdbid.is_some() {
             let tvdbid = info.tvdbid.as_ref().unwrap().clone();
@@ -73,23 +78,25 @@ impl TorznabProvider {
             wrapper = global::RB.new_wrapper().limit(1);
         }
 
-        let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await.unwrap();
-
-        let tv = tv.unwrap();
-        let tv_id = tv.id.unwrap();
+        let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await?;
 
         let req = page(info.offset.unwrap_or(0_u64), info.limit.unwrap_or(50_u64));
 
-        let mut wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), tv_id);
+        // an unknown show answers an empty feed
+        let seeds: Page<TvSeed> = match tv.as_ref().and_then(|tv| tv.id) {
+            Some(tv_id) => {
+                let mut wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), tv_id);
 
-        if info.ep.is_some() {
-            wrapper = wrapper.eq(TvSeed::ep(), info.ep.unwrap_or(-1));
-        }
+                if info.ep.is_some() {
+                    wrapper = wrapper.eq(TvSeed::ep(), info.ep.unwrap_or(-1));
+                }
+                // pinned releases first
+                wrapper = wrapper.order_by(false, &[TvSeed::pinned()]);
 
-        let seeds: Page<TvSeed> = global::RB
-            .fetch_page_by_wrapper(wrapper, &req)
-            .await
-            .unwrap();
+                global::RB.fetch_page_by_wrapper(wrapper, &req).await?
+            }
+            None => Page::new(req.page_no, req.page_size),
+        };
 
         if seeds.total == 0 && info.ep.is_some() && info.tvdbid.is_some() {
             let tvdbid = info.tvdbid.as_ref().unwrap().clone();
@@ -112,9 +119,19 @@ impl TorznabProvider {
         Self::createElementWithChars(&mut writer, "language", "zh-CN");
         Self::createElementWithChars(&mut writer, "category", "search");
 
+        // download links are checked like the api when a key is set
+        let api_key = auth::api_key();
+        let show_trackers = trackers::for_tv(tv.as_ref());
         for seed in seeds.records {
+            let mut download_url = format!("{}/download/{}", base_url, seed.id.unwrap());
+            if let Some(api_key) = &api_key {
+                download_url = format!("{}?apikey={}", download_url, urlencoding::encode(api_key));
+            }
             let name = seed.name.unwrap();
-            let url = seed.url.unwrap();
+            let url = trackers::decorate(&seed.url.unwrap(), &show_trackers);
+            let size = seed.size.unwrap_or(0).to_string();
+            let season = seed.season.map(|season| season.to_string());
+            let episode = seed.ep.filter(|ep| *ep >= 0).map(|ep| ep.to_string());
 
             writer.write(XmlEvent::start_element("item"));
             Self::createElementWithChars(&mut writer, "title", &name);
@@ -124,19 +141,15 @@ impl TorznabProvider {
                 xml::escape::escape_str_attribute(&url).as_ref(),
             );
             Self::createElementWithChars(&mut writer, "pubDate", &pub_date());
-            Self::createElementWithChars(&mut writer, "size", "0");
-            Self::createElementWithChars(
-                &mut writer,
-                "link",
-                xml::escape::escape_str_attribute(&url).as_ref(),
-            );
+            Self::createElementWithChars(&mut writer, "size", &size);
+            Self::createElementWithChars(&mut writer, "link", &download_url);
             Self::createElementWithChars(&mut writer, "category", "5000");
 
             writer.write(
                 XmlEvent::start_element("enclosure")
-                    .attr("url", xml::escape::escape_str_attribute(&url).as_ref())
-                    .attr("type", "application/x-bittorrent")
-                    .attr("length", "0"),
+                    .attr("url", &download_url)
+                    .attr("type", enclosure_type(&url))
+                    .attr("length", &size),
             );
             writer.write(XmlEvent::end_element());
 
@@ -147,12 +160,59 @@ impl TorznabProvider {
             );
             writer.write(XmlEvent::end_element());
 
+            writer.write(
+                XmlEvent::start_element("torznab:attr")
+                    .attr("name", "magneturl")
+                    .attr("value", &url),
+            );
+            writer.write(XmlEvent::end_element());
+
+            if let Some(season) = &season {
+                writer.write(
+                    XmlEvent::start_element("torznab:attr")
+                        .attr("name", "season")
+                        .attr("value", season),
+                );
+                writer.write(XmlEvent::end_element());
+            }
+
+            if let Some(episode) = &episode {
+                writer.write(
+                    XmlEvent::start_element("torznab:attr")
+                        .attr("name", "episode")
+                        .attr("value", episode),
+                );
+                writer.write(XmlEvent::end_element());
+            }
+
+            if let Some(info_hash) = link::info_hash(&url) {
+                writer.write(
+                    XmlEvent::start_element("torznab:attr")
+                        .attr("name", "infohash")
+                        .attr("value", &info_hash),
+                );
+                writer.write(XmlEvent::end_element());
+            }
+
             writer.write(XmlEvent::end_element());
         }
 
         writer.write(XmlEvent::end_element());
         writer.write(XmlEvent::end_element());
 
+        Ok(std::str::from_utf8(&target).unwrap().to_string())
+    }
+
+    /// The Torznab error body, code 900 being the spec's unknown error.
+    pub fn error(&self, code: u32, description: &str) -> String {
+        let mut target: Vec<u8> = Vec::new();
+        let mut writer = EmitterConfig::new().create_writer(&mut target);
+        writer.write(
+            XmlEvent::start_element("error")
+                .attr("code", &code.to_string())
+                .attr("description", description),
+        );
+        writer.write(XmlEvent::end_element());
         std::str::from_utf8(&target).unwrap().to_string()
     }
 
@@ -162,11 +222,11 @@ impl TorznabProvider {
         writer.write(XmlEvent::end_element());
     }
 
-    pub async fn handle(&self, info: &web::Query<ApiRequest>) -> String {
+    pub async fn handle(&self, info: &web::Query<ApiRequest>, base_url: &str) -> Result<String> {
         if info.t == "caps" {
-            self.caps()
+            Ok(self.caps())
         } else {
-            self.search(info).await
+            self.search(info, base_url).await
         }
     }
 }
@@ -179,6 +239,14 @@ fn page(offset: u64, page_size: u64) -> PageRequest {
     PageRequest::new(page_no + 1, page_size)
 }
 
+/// `/download/{id}` serves cached torrents and redirects to the magnet otherwise.
+fn enclosure_type(magnet: &str) -> &'static str {
+    match link::info_hash(magnet) {
+        Some(info_hash) if cache::contains(&info_hash) => "application/x-bittorrent",
+        _ => "application/x-bittorrent;x-scheme-handler/magnet",
+    }
+}
+
 fn pub_date() -> String {
     let time = Utc::now() - Duration::days(1);
     time.to_rfc2822()
@@ -209,6 +277,14 @@ mod tests {
         assert_eq!(page.page_no, 9999);
     }
 
+    #[test]
+    fn test_enclosure_type() {
+        assert_eq!(
+            enclosure_type("magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567"),
+            "application/x-bittorrent;x-scheme-handler/magnet"
+        );
+    }
+
     #[test]
     fn test_pub_date() {
         let _date = pub_date();
@@ -221,6 +297,14 @@ mod tests {
         assert!(provider.caps().contains("<caps>"));
     }
 
+    #[test]
+    fn test_error() {
+        let provider = TorznabProvider::new();
+        let body = provider.error(900, "database error: \"locked\" & busy");
+        assert!(body.contains(r#"<error code="900""#));
+        assert!(body.contains(r#"description="database error: &quot;locked&quot; &amp; busy""#));
+    }
+
     #[test]
     fn test_caps() {
         let provider = TorznabProvider::new();
@@ -0,0 +1,91 @@
+use std::fs;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use log::info;
+
+use crate::define;
+use crate::model::Tv;
+
+lazy_static! {
+    static ref GLOBAL: RwLock<Vec<String>> = RwLock::new(vec![]);
+}
+
+/// Loads the global tracker list, one tracker per line, `#` starts a comment.
+pub fn init(file: Option<&str>) -> Result<()> {
+    let trackers = match file {
+        Some(file) => parse(&fs::read_to_string(file)?),
+        None => vec![],
+    };
+    info!("global tracker size:{}", trackers.len());
+    *GLOBAL.write().unwrap() = trackers;
+    Ok(())
+}
+
+pub fn parse(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Appends trackers missing from a magnet.
+pub fn decorate(magnet: &str, trackers: &[String]) -> String {
+    let mut magnet = magnet.to_string();
+    for tracker in trackers {
+        let tr = format!("tr={}", urlencoding::encode(tracker));
+        if !magnet.contains(&tr) && !magnet.contains(&format!("tr={}", tracker)) {
+            magnet.push('&');
+            magnet.push_str(&tr);
+        }
+    }
+    magnet
+}
+
+/// The global trackers and the trackers of the show's define, looked up once per show.
+pub fn for_tv(tv: Option<&Tv>) -> Vec<String> {
+    let mut trackers = GLOBAL.read().unwrap().clone();
+    let define = tv.and_then(|tv| tv.url.as_deref()).and_then(define::find);
+    if let Some(options) = define.and_then(|d| d.magnet) {
+        trackers.extend(options.trackers);
+    }
+    trackers
+}
+
+/// Adds the global trackers and the trackers of the show's define to a stored magnet.
+pub fn render(tv: Option<&Tv>, magnet: &str) -> String {
+    decorate(magnet, &for_tv(tv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let trackers = parse(
+            "# public\nudp://a.example:6969/announce\n\n  http://b.example/announce # backup\n",
+        );
+
+        assert_eq!(
+            trackers,
+            vec!["udp://a.example:6969/announce", "http://b.example/announce"]
+        );
+    }
+
+    #[test]
+    fn test_decorate() {
+        let magnet = "magnet:?xt=urn:btih:abc&tr=udp%3A%2F%2Fa.example%3A6969%2Fannounce";
+        let trackers = vec![
+            "udp://a.example:6969/announce".to_string(),
+            "http://b.example/announce".to_string(),
+        ];
+
+        assert_eq!(
+            decorate(magnet, &trackers),
+            format!("{}&tr=http%3A%2F%2Fb.example%2Fannounce", magnet)
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use rbatis::crud::CRUD;
+use rbatis::wrapper::Wrapper;
+
+use crate::define;
+use crate::error::{AppError, Result};
+use crate::global;
+use crate::model::{self, Tv, TvSeed};
+
+/// Field problems of a show that can be found without the database.
+pub fn check_tv(tv: &Tv) -> HashMap<String, String> {
+    let mut errors = HashMap::new();
+    if tv
+        .name
+        .as_deref()
+        .map_or(true, |name| name.trim().is_empty())
+    {
+        errors.insert("name".to_string(), "name is required".to_string());
+    }
+    if tv
+        .tvname
+        .as_deref()
+        .map_or(true, |tvname| tvname.trim().is_empty())
+    {
+        errors.insert("tvname".to_string(), "tvname is required".to_string());
+    }
+    match tv.tvdbid.as_deref() {
+        None | Some("") => {
+            errors.insert("tvdbid".to_string(), "tvdbid is required".to_string());
+        }
+        Some(tvdbid) if !tvdbid.chars().all(|c| c.is_ascii_digit()) => {
+            errors.insert("tvdbid".to_string(), "tvdbid must be a number".to_string());
+        }
+        _ => {}
+    }
+    if let Some(url) = tv.url.as_deref() {
+        if define::find(url).is_none() {
+            errors.insert(
+                "url".to_string(),
+                format!("no define matches the domain of {}", url),
+            );
+        }
+    }
+    errors
+}
+
+pub async fn validate_tv(tv: &Tv) -> Result<()> {
+    let mut errors = check_tv(tv);
+    if !errors.contains_key("tvdbid") {
+        let wrapper = global::RB
+            .new_wrapper()
+            .eq(Tv::tvdbid(), tv.tvdbid.as_ref().unwrap());
+        let existing: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await?;
+        if let Some(existing) = existing.filter(|existing| existing.id != tv.id) {
+            errors.insert(
+                "tvdbid".to_string(),
+                format!(
+                    "tvdbid is already used by {}",
+                    existing.name.unwrap_or_default()
+                ),
+            );
+        }
+    }
+    if !errors.is_empty() {
+        return Err(AppError::Invalid(errors));
+    }
+    Ok(())
+}
+
+/// Validates and inserts or updates a show.
+pub async fn save_tv(mut tv: Tv) -> Result<()> {
+    validate_tv(&tv).await?;
+
+    tv.updated_at = Some(model::timestamp());
+    if tv.id.is_some() {
+        global::RB.update_by_column(Tv::id(), &tv).await?;
+    } else {
+        global::RB.save(&tv, &[]).await?;
+    }
+    Ok(())
+}
+
+/// Makes `%` and `_` in a keyword match themselves in a `LIKE ... ESCAPE '\'` pattern.
+pub(crate) fn escape_like(keyword: &str) -> String {
+    keyword
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Shows whose names or aliases contain the keyword, or with it as tvdbid.
+pub fn tv_filter(keyword: Option<String>) -> Wrapper {
+    let wrapper = global::RB.new_wrapper();
+    match non_empty(keyword) {
+        Some(keyword) => {
+            let like = format!("%{}%", escape_like(&keyword));
+            wrapper
+                .push_sql(
+                    "(name LIKE ? ESCAPE '\\' OR tvname LIKE ? ESCAPE '\\' \
+                     OR aliases LIKE ? ESCAPE '\\' OR tvdbid = ?)",
+                )
+                .push_arg(&like)
+                .push_arg(&like)
+                .push_arg(&like)
+                .push_arg(&keyword)
+        }
+        None => wrapper,
+    }
+}
+
+pub async fn find_tv(id: i64) -> Result<Tv> {
+    let wrapper = global::RB.new_wrapper().eq(Tv::id(), id);
+    let tv: Option<Tv> = global::RB.fetch_by_wrapper(wrapper).await?;
+    tv.ok_or(AppError::NotFound("tv"))
+}
+
+/// Empty strings clear optional fields.
+pub fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Deletes a show with its seeds.
+pub async fn remove_tv(id: i64) -> Result<()> {
+    find_tv(id).await?;
+
+    let wrapper = global::RB.new_wrapper().eq(TvSeed::tv_id(), id);
+    global::RB.remove_by_wrapper::<TvSeed>(wrapper).await?;
+    let wrapper = global::RB.new_wrapper().eq(Tv::id(), id);
+    global::RB.remove_by_wrapper::<Tv>(wrapper).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("100%_done"), "100\\%\\_done");
+        assert_eq!(escape_like("a\\b"), "a\\\\b");
+        assert_eq!(escape_like("三体"), "三体");
+    }
+
+    #[test]
+    fn test_check_tv() {
+        let tv = Tv {
+            id: None,
+            tvdbid: Some("81189".to_string()),
+            tvname: Some("Breaking Bad".to_string()),
+            url: Some("https://www.domp4.cc/html/abc.html".to_string()),
+            name: Some("绝命毒师".to_string()),
+            aliases: None,
+            updated_at: None,
+            sonarr_id: None,
+            removed: None,
+        };
+        assert!(check_tv(&tv).is_empty());
+
+        let errors = check_tv(&Tv {
+            tvdbid: Some("tt0903747".to_string()),
+            url: Some("https://unknown.example/abc".to_string()),
+            name: Some(" ".to_string()),
+            tvname: None,
+            ..tv
+        });
+        assert_eq!(errors["tvdbid"], "tvdbid must be a number");
+        assert_eq!(errors["name"], "name is required");
+        assert_eq!(errors["tvname"], "tvname is required");
+        assert!(errors["url"].starts_with("no define matches"));
+    }
+}